@@ -0,0 +1,236 @@
+use core::arch::asm;
+
+#[inline]
+pub fn halt() {
+    unsafe {
+        asm!("hlt", options(nomem, nostack, preserves_flags));
+    }
+}
+
+pub fn halt_forever() -> ! {
+    unsafe {
+        asm!("cli", options(nomem, nostack));
+    }
+
+    loop {
+        halt();
+    }
+}
+
+#[inline]
+pub fn read_cs() -> u16 {
+    let selector: u16;
+    unsafe {
+        asm!(
+            "mov {0:x}, cs",
+            out(reg) selector,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    selector
+}
+
+#[inline(always)]
+pub fn read_esp() -> u32 {
+    let esp: usize;
+    unsafe {
+        asm!(
+            "mov {}, esp",
+            out(reg) esp,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    esp as u32
+}
+
+#[inline(always)]
+pub fn read_ebp() -> u32 {
+    let ebp: usize;
+    unsafe {
+        asm!(
+            "mov {}, ebp",
+            out(reg) ebp,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    ebp as u32
+}
+
+#[inline(always)]
+pub fn read_cr0() -> u32 {
+    let value: usize;
+    unsafe {
+        asm!(
+            "mov {}, cr0",
+            out(reg) value,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    value as u32
+}
+
+#[inline(always)]
+pub fn read_cr2() -> u32 {
+    let value: usize;
+    unsafe {
+        asm!(
+            "mov {}, cr2",
+            out(reg) value,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    value as u32
+}
+
+#[inline(always)]
+pub fn read_cr3() -> u32 {
+    let value: usize;
+    unsafe {
+        asm!(
+            "mov {}, cr3",
+            out(reg) value,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    value as u32
+}
+
+// Writes to control registers can change how memory is translated, so they are not `nomem`
+pub fn write_cr0(value: u32) {
+    unsafe {
+        asm!("mov cr0, {}", in(reg) value as usize, options(nostack, preserves_flags));
+    }
+}
+
+pub fn write_cr3(value: u32) {
+    unsafe {
+        asm!("mov cr3, {}", in(reg) value as usize, options(nostack, preserves_flags));
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Registers {
+    pub eax: u32,
+    pub ebx: u32,
+    pub ecx: u32,
+    pub edx: u32,
+    pub esi: u32,
+    pub edi: u32,
+    pub ebp: u32,
+    pub esp: u32,
+    pub eflags: u32,
+    pub cs: u16,
+    pub ds: u16,
+    pub ss: u16,
+    pub cr0: u32,
+    pub cr2: u32,
+    pub cr3: u32,
+}
+
+impl Registers {
+    // General purpose registers hold whatever the compiler left in them at the call site
+    #[inline(always)]
+    pub fn capture() -> Self {
+        // Stored through memory because ESI and EBX cannot be named as asm operands on x86
+        let mut general = [0u32; 6];
+        let eflags: usize;
+        let (ds, ss): (u16, u16);
+        unsafe {
+            asm!(
+                "mov [{0}], eax",
+                "mov [{0} + 4], ebx",
+                "mov [{0} + 8], ecx",
+                "mov [{0} + 12], edx",
+                "mov [{0} + 16], esi",
+                "mov [{0} + 20], edi",
+                in(reg) general.as_mut_ptr(),
+                options(nostack, preserves_flags)
+            );
+            asm!(
+                "pushfd",
+                "pop {}",
+                out(reg) eflags,
+                options(nomem, preserves_flags)
+            );
+            asm!(
+                "mov {0:x}, ds",
+                "mov {1:x}, ss",
+                out(reg) ds,
+                out(reg) ss,
+                options(nomem, nostack, preserves_flags)
+            );
+        }
+
+        Self {
+            eax: general[0],
+            ebx: general[1],
+            ecx: general[2],
+            edx: general[3],
+            esi: general[4],
+            edi: general[5],
+            ebp: read_ebp(),
+            esp: read_esp(),
+            eflags: eflags as u32,
+            cs: read_cs(),
+            ds,
+            ss,
+            cr0: read_cr0(),
+            cr2: read_cr2(),
+            cr3: read_cr3(),
+        }
+    }
+}
+
+#[inline]
+pub fn enable_interrupts() {
+    unsafe {
+        asm!("sti", options(nostack));
+    }
+}
+
+#[inline]
+pub fn disable_interrupts() {
+    unsafe {
+        asm!("cli", options(nostack));
+    }
+}
+
+#[inline]
+pub fn interrupts_enabled() -> bool {
+    let eflags: usize;
+    unsafe {
+        asm!(
+            "pushfd",
+            "pop {}",
+            out(reg) eflags,
+            options(nomem, preserves_flags)
+        );
+    }
+    eflags & (1 << 9) != 0
+}
+
+// `sti` only takes effect after the following instruction, so an interrupt arriving
+// between the caller's last check and `hlt` still wakes the CPU up.
+#[inline]
+pub fn enable_interrupts_and_halt() {
+    unsafe {
+        asm!("sti", "hlt", options(nostack));
+    }
+}
+
+pub fn without_interrupts<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let were_enabled = interrupts_enabled();
+    if were_enabled {
+        disable_interrupts();
+    }
+
+    let result = f();
+
+    if were_enabled {
+        enable_interrupts();
+    }
+    result
+}
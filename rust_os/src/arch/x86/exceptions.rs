@@ -0,0 +1,157 @@
+use crate::printk;
+use crate::screen::global::screen_manager;
+use super::idt::{self, InterruptStackFrame};
+use super::page_fault::page_fault_handler;
+
+const EXCEPTION_NAMES: [(&str, &str); 32] = [
+    ("#DE", "Divide Error"),
+    ("#DB", "Debug"),
+    ("NMI", "Non-Maskable Interrupt"),
+    ("#BP", "Breakpoint"),
+    ("#OF", "Overflow"),
+    ("#BR", "BOUND Range Exceeded"),
+    ("#UD", "Invalid Opcode"),
+    ("#NM", "Device Not Available"),
+    ("#DF", "Double Fault"),
+    ("---", "Coprocessor Segment Overrun"),
+    ("#TS", "Invalid TSS"),
+    ("#NP", "Segment Not Present"),
+    ("#SS", "Stack-Segment Fault"),
+    ("#GP", "General Protection Fault"),
+    ("#PF", "Page Fault"),
+    ("---", "Reserved"),
+    ("#MF", "x87 Floating-Point Exception"),
+    ("#AC", "Alignment Check"),
+    ("#MC", "Machine Check"),
+    ("#XM", "SIMD Floating-Point Exception"),
+    ("#VE", "Virtualization Exception"),
+    ("#CP", "Control Protection Exception"),
+    ("---", "Reserved"),
+    ("---", "Reserved"),
+    ("---", "Reserved"),
+    ("---", "Reserved"),
+    ("---", "Reserved"),
+    ("---", "Reserved"),
+    ("#HV", "Hypervisor Injection Exception"),
+    ("#VC", "VMM Communication Exception"),
+    ("#SX", "Security Exception"),
+    ("---", "Reserved"),
+];
+
+macro_rules! exception_handler {
+    ($name:ident, $vector:expr) => {
+        extern "x86-interrupt" fn $name(frame: InterruptStackFrame) {
+            report_exception($vector, None, &frame);
+        }
+    };
+    ($name:ident, $vector:expr, error_code) => {
+        extern "x86-interrupt" fn $name(frame: InterruptStackFrame, error_code: u32) {
+            report_exception($vector, Some(error_code), &frame);
+        }
+    };
+}
+
+exception_handler!(divide_error, 0);
+exception_handler!(debug, 1);
+exception_handler!(non_maskable_interrupt, 2);
+exception_handler!(breakpoint, 3);
+exception_handler!(overflow, 4);
+exception_handler!(bound_range_exceeded, 5);
+exception_handler!(invalid_opcode, 6);
+exception_handler!(device_not_available, 7);
+exception_handler!(double_fault, 8, error_code);
+exception_handler!(coprocessor_segment_overrun, 9);
+exception_handler!(invalid_tss, 10, error_code);
+exception_handler!(segment_not_present, 11, error_code);
+exception_handler!(stack_segment_fault, 12, error_code);
+exception_handler!(general_protection_fault, 13, error_code);
+exception_handler!(reserved_15, 15);
+exception_handler!(x87_floating_point, 16);
+exception_handler!(alignment_check, 17, error_code);
+exception_handler!(machine_check, 18);
+exception_handler!(simd_floating_point, 19);
+exception_handler!(virtualization, 20);
+exception_handler!(control_protection, 21, error_code);
+exception_handler!(reserved_22, 22);
+exception_handler!(reserved_23, 23);
+exception_handler!(reserved_24, 24);
+exception_handler!(reserved_25, 25);
+exception_handler!(reserved_26, 26);
+exception_handler!(reserved_27, 27);
+exception_handler!(hypervisor_injection, 28);
+exception_handler!(vmm_communication, 29, error_code);
+exception_handler!(security_exception, 30, error_code);
+exception_handler!(reserved_31, 31);
+
+pub fn install_exception_handlers() {
+    idt::set_handler(0, divide_error);
+    idt::set_handler(1, debug);
+    idt::set_handler(2, non_maskable_interrupt);
+    idt::set_handler(3, breakpoint);
+    idt::set_handler(4, overflow);
+    idt::set_handler(5, bound_range_exceeded);
+    idt::set_handler(6, invalid_opcode);
+    idt::set_handler(7, device_not_available);
+    idt::set_handler_with_err_code(8, double_fault);
+    idt::set_handler(9, coprocessor_segment_overrun);
+    idt::set_handler_with_err_code(10, invalid_tss);
+    idt::set_handler_with_err_code(11, segment_not_present);
+    idt::set_handler_with_err_code(12, stack_segment_fault);
+    idt::set_handler_with_err_code(13, general_protection_fault);
+    idt::set_handler_with_err_code(14, page_fault_handler);
+    idt::set_handler(15, reserved_15);
+    idt::set_handler(16, x87_floating_point);
+    idt::set_handler_with_err_code(17, alignment_check);
+    idt::set_handler(18, machine_check);
+    idt::set_handler(19, simd_floating_point);
+    idt::set_handler(20, virtualization);
+    idt::set_handler_with_err_code(21, control_protection);
+    idt::set_handler(22, reserved_22);
+    idt::set_handler(23, reserved_23);
+    idt::set_handler(24, reserved_24);
+    idt::set_handler(25, reserved_25);
+    idt::set_handler(26, reserved_26);
+    idt::set_handler(27, reserved_27);
+    idt::set_handler(28, hypervisor_injection);
+    idt::set_handler_with_err_code(29, vmm_communication);
+    idt::set_handler_with_err_code(30, security_exception);
+    idt::set_handler(31, reserved_31);
+}
+
+// Traps report the instruction after the one that raised them, so execution can resume.
+// Everything else would fault again on return.
+fn is_resumable(vector: u8) -> bool {
+    matches!(vector, 1 | 3 | 4)
+}
+
+// Fatal vectors go straight to the panic screen, which draws without the screen lock. The
+// fault may have hit while that lock was held, so printk could spin forever.
+fn report_exception(vector: u8, error_code: Option<u32>, frame: &InterruptStackFrame) {
+    let (mnemonic, name) = EXCEPTION_NAMES[vector as usize];
+
+    if !is_resumable(vector) {
+        match error_code {
+            Some(code) => panic!(
+                "EXCEPTION {} {} (vector {}), error code {:#010x}, EIP={:#010x} CS={:#06x} EFLAGS={:#010x}",
+                mnemonic, name, vector, code, frame.eip, frame.cs, frame.eflags
+            ),
+            None => panic!(
+                "EXCEPTION {} {} (vector {}), EIP={:#010x} CS={:#06x} EFLAGS={:#010x}",
+                mnemonic, name, vector, frame.eip, frame.cs, frame.eflags
+            ),
+        }
+    }
+
+    // A trap can also fire inside screen code, with the screen lock held by the code it
+    // interrupted. The report is then dropped rather than spinning on that lock. Interrupts
+    // are off in the handler, so nothing can take the lock between the check and printk.
+    if screen_manager().try_lock().is_none() {
+        return;
+    }
+    printk!(LogLevel::Critical, "EXCEPTION {} {} (vector {})\n", mnemonic, name, vector);
+    printk!(
+        LogLevel::Critical,
+        "  EIP={:#010x} CS={:#06x} EFLAGS={:#010x}\n",
+        frame.eip, frame.cs, frame.eflags
+    );
+}
\ No newline at end of file
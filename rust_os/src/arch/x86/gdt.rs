@@ -1,10 +1,22 @@
 use core::arch::asm;
+use core::fmt;
+use core::mem::size_of;
+use core::ptr;
 
-const KERNEL_DATA_SEGMENT_SELECTOR: u16 = 0x10;
-#[allow(dead_code)]
-const KERNEL_CODE_SEGMENT_SELECTOR: u16 = 0x08;
-#[allow(dead_code)]
-const USER_CODE_SEGMENT_SELECTOR: u16 = 0x08 | 0x03;
+pub const KERNEL_CODE_SELECTOR: u16 = 0x08;
+pub const KERNEL_DATA_SELECTOR: u16 = 0x10;
+pub const KERNEL_STACK_SELECTOR: u16 = 0x18;
+pub const USER_CODE_SELECTOR: u16 = 0x20 | 0x03;
+pub const USER_DATA_SELECTOR: u16 = 0x28 | 0x03;
+pub const USER_STACK_SELECTOR: u16 = 0x30 | 0x03;
+pub const TSS_SELECTOR: u16 = 0x38;
+
+const GDT_SIZE: usize = 8;
+const TSS_INDEX: usize = 7;
+
+const SYSTEM_TYPE_LDT: u8 = 0x2;
+const SYSTEM_TYPE_TSS_AVAILABLE_32: u8 = 0x9;
+const SYSTEM_TYPE_TSS_BUSY_32: u8 = 0xB;
 
 #[repr(C, packed)]
 struct GdtDescriptor {
@@ -13,6 +25,7 @@ struct GdtDescriptor {
 }
 
 #[repr(C, packed)]
+#[derive(Clone, Copy)]
 struct SegmentDescriptor {
     limit_low: u16,
     base_low: u16,
@@ -22,28 +35,160 @@ struct SegmentDescriptor {
     base_high: u8,
 }
 
-const GDT_SIZE: usize = 7;
-const GDT_ADDRESS: u32 = 0x0000_0800;
+#[derive(Clone, Copy, Default)]
+pub struct Access(u8);
+
+impl Access {
+    const PRESENT: u8 = 1 << 7;
+    const CODE_OR_DATA: u8 = 1 << 4;
+    const EXECUTABLE: u8 = 1 << 3;
+    const DIRECTION_CONFORMING: u8 = 1 << 2;
+    const READ_WRITE: u8 = 1 << 1;
+
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    pub const fn present(self) -> Self {
+        Self(self.0 | Self::PRESENT)
+    }
+
+    pub const fn privilege(self, ring: u8) -> Self {
+        Self((self.0 & !0x60) | ((ring & 0x03) << 5))
+    }
+
+    // Code and data segments, as opposed to system descriptors like the TSS
+    pub const fn code_or_data(self) -> Self {
+        Self(self.0 | Self::CODE_OR_DATA)
+    }
+
+    pub const fn executable(self) -> Self {
+        Self(self.0 | Self::EXECUTABLE)
+    }
+
+    // Readable for code segments, writable for data segments
+    pub const fn read_write(self) -> Self {
+        Self(self.0 | Self::READ_WRITE)
+    }
+
+    // Conforming for code segments, expand-down for data segments
+    pub const fn direction_conforming(self) -> Self {
+        Self(self.0 | Self::DIRECTION_CONFORMING)
+    }
+
+    pub const fn system_type(self, kind: u8) -> Self {
+        Self((self.0 & !0x1F) | (kind & 0x0F))
+    }
+
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct Granularity(u8);
+
+impl Granularity {
+    const PAGE_GRANULAR: u8 = 1 << 7;
+    const PROTECTED_32: u8 = 1 << 6;
+
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    // The limit counts 4 KiB pages instead of bytes
+    pub const fn page_granular(self) -> Self {
+        Self(self.0 | Self::PAGE_GRANULAR)
+    }
+
+    pub const fn protected_32(self) -> Self {
+        Self(self.0 | Self::PROTECTED_32)
+    }
+
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+}
+
+#[repr(C, packed)]
+pub struct TaskStateSegment {
+    prev_task_link: u32,
+    esp0: u32,
+    ss0: u32,
+    esp1: u32,
+    ss1: u32,
+    esp2: u32,
+    ss2: u32,
+    cr3: u32,
+    eip: u32,
+    eflags: u32,
+    eax: u32,
+    ecx: u32,
+    edx: u32,
+    ebx: u32,
+    esp: u32,
+    ebp: u32,
+    esi: u32,
+    edi: u32,
+    es: u32,
+    cs: u32,
+    ss: u32,
+    ds: u32,
+    fs: u32,
+    gs: u32,
+    ldtr: u32,
+    trap: u16,
+    iomap_base: u16,
+}
 
-static mut GDT: *mut [SegmentDescriptor; GDT_SIZE] =
-    GDT_ADDRESS as *mut [SegmentDescriptor; GDT_SIZE];
+const FLAT_LIMIT: u32 = 0xFFFFF;
+const FLAT_GRANULARITY: Granularity = Granularity::new().page_granular().protected_32();
+
+const fn code_segment(ring: u8) -> SegmentDescriptor {
+    let access = Access::new().present().privilege(ring).code_or_data().executable().read_write();
+    SegmentDescriptor::new(0, FLAT_LIMIT, access, FLAT_GRANULARITY)
+}
+
+const fn data_segment(ring: u8) -> SegmentDescriptor {
+    let access = Access::new().present().privilege(ring).code_or_data().read_write();
+    SegmentDescriptor::new(0, FLAT_LIMIT, access, FLAT_GRANULARITY)
+}
+
+// Expand-down segments are valid above their limit, so a limit of 0 spans all but the first page
+const fn stack_segment(ring: u8) -> SegmentDescriptor {
+    let access = Access::new().present().privilege(ring).code_or_data().read_write().direction_conforming();
+    SegmentDescriptor::new(0, 0, access, FLAT_GRANULARITY)
+}
+
+static mut GDT: [SegmentDescriptor; GDT_SIZE] = [
+    SegmentDescriptor::null(),  // Null segment
+    code_segment(0),            // Kernel code segment
+    data_segment(0),            // Kernel data segment
+    stack_segment(0),           // Kernel stack segment
+    code_segment(3),            // User code segment
+    data_segment(3),            // User data segment
+    stack_segment(3),           // User stack segment
+    SegmentDescriptor::null(),  // Task state segment, filled in by init_gdt
+];
+
+static mut TSS: TaskStateSegment = TaskStateSegment::new();
 
 pub fn init_gdt() {
     unsafe {
-        *GDT = [
-            SegmentDescriptor::null(),                // Null segment
-            SegmentDescriptor::new(0, 0xFFFFF, 0x9A), // Kernel code segment
-            SegmentDescriptor::new(0, 0xFFFFF, 0x93), // Kernel data segment
-            SegmentDescriptor::new(0, 0xFFFFF, 0x96), // Kernel stack segment
-            SegmentDescriptor::new(0, 0xFFFFF, 0xFA), // User code segment
-            SegmentDescriptor::new(0, 0xFFFFF, 0xF2), // User data segment
-            SegmentDescriptor::new(0, 0xFFFFF, 0xF6), // User stack segment
-        ];
+        let gdt = &raw mut GDT;
+        let tss = &raw const TSS;
+
+        (*gdt)[TSS_INDEX] = SegmentDescriptor::new(
+            tss as usize as u32,
+            (size_of::<TaskStateSegment>() - 1) as u32,
+            Access::new().present().system_type(SYSTEM_TYPE_TSS_AVAILABLE_32),
+            Granularity::new(),
+        );
 
         let gdtr = GdtDescriptor {
             #[allow(clippy::cast_possible_truncation)]
             limit: (size_of::<[SegmentDescriptor; GDT_SIZE]>() - 1) as u16,
-            base: GDT_ADDRESS,
+            base: gdt as usize as u32,
         };
 
         asm!(
@@ -54,28 +199,200 @@ pub fn init_gdt() {
 
         // Reload segment selectors
         asm!(
-            "mov ax, {selector}",
-            "mov ds, ax",
-            "mov es, ax",
-            "mov fs, ax",
-            "mov gs, ax",
-            "mov ss, ax",
-            options(nostack),
-            selector = const KERNEL_DATA_SEGMENT_SELECTOR
+            "mov ds, {selector:x}",
+            "mov es, {selector:x}",
+            "mov fs, {selector:x}",
+            "mov gs, {selector:x}",
+            "mov ss, {selector:x}",
+            selector = in(reg) KERNEL_DATA_SELECTOR,
+            options(nostack, preserves_flags)
+        );
+
+        // CS can only be reloaded by a far transfer
+        asm!(
+            "push {selector}",
+            "lea {tmp}, [2f]",
+            "push {tmp}",
+            "retf",
+            "2:",
+            selector = const KERNEL_CODE_SELECTOR,
+            tmp = out(reg) _,
+            options(preserves_flags)
+        );
+
+        asm!(
+            "ltr {0:x}",
+            in(reg) TSS_SELECTOR,
+            options(nostack, preserves_flags)
+        );
+    }
+}
+
+// Stack loaded by the CPU when an interrupt moves from ring 3 to ring 0
+pub fn set_kernel_stack(esp0: u32) {
+    unsafe {
+        let tss = &raw mut TSS;
+        (*tss).esp0 = esp0;
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Gdtr {
+    pub base: u32,
+    pub limit: u16,
+}
+
+impl Gdtr {
+    pub fn entry_count(&self) -> usize {
+        (self.limit as usize + 1) / size_of::<SegmentDescriptor>()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum DescriptorKind {
+    Null,
+    Code { readable: bool, conforming: bool },
+    Data { writable: bool, expand_down: bool },
+    Ldt,
+    Tss { busy: bool },
+    System(u8),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DecodedDescriptor {
+    pub index: usize,
+    pub selector: u16,
+    pub base: u32,
+    // Effective limit in bytes, with the granularity already applied
+    pub limit: u32,
+    pub dpl: u8,
+    pub present: bool,
+    pub kind: DescriptorKind,
+    pub access: u8,
+    // Upper nibble of the granularity byte: G, D/B, L, AVL
+    pub flags: u8,
+}
+
+impl DecodedDescriptor {
+    pub fn page_granular(&self) -> bool {
+        self.flags & 0x8 != 0
+    }
+
+    pub fn protected_32(&self) -> bool {
+        self.flags & 0x4 != 0
+    }
+
+    pub fn long_mode(&self) -> bool {
+        self.flags & 0x2 != 0
+    }
+
+    pub fn available(&self) -> bool {
+        self.flags & 0x1 != 0
+    }
+}
+
+impl DescriptorKind {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            DescriptorKind::Null => "null",
+            DescriptorKind::Code { readable: false, conforming: false } => "code x--",
+            DescriptorKind::Code { readable: true, conforming: false } => "code xr-",
+            DescriptorKind::Code { readable: false, conforming: true } => "code x-c",
+            DescriptorKind::Code { readable: true, conforming: true } => "code xrc",
+            DescriptorKind::Data { writable: false, expand_down: false } => "data r--",
+            DescriptorKind::Data { writable: true, expand_down: false } => "data rw-",
+            DescriptorKind::Data { writable: false, expand_down: true } => "data r-e",
+            DescriptorKind::Data { writable: true, expand_down: true } => "data rwe",
+            DescriptorKind::Ldt => "ldt",
+            DescriptorKind::Tss { busy: false } => "tss32 avl",
+            DescriptorKind::Tss { busy: true } => "tss32 busy",
+            DescriptorKind::System(_) => "system",
+        }
+    }
+}
+
+impl fmt::Display for DescriptorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+pub fn read_gdtr() -> Gdtr {
+    let mut gdtr = GdtDescriptor { limit: 0, base: 0 };
+    unsafe {
+        asm!(
+            "sgdt [{}]",
+            in(reg) &raw mut gdtr,
+            options(nostack, preserves_flags)
         );
+    }
 
-        asm!("push 0x08", "lea eax, [2f]", "push eax", "retf", "2:",);
+    Gdtr {
+        base: gdtr.base,
+        limit: gdtr.limit,
     }
 }
 
+pub fn analyse_gdt_entry(gdtr: &Gdtr, index: usize) -> Option<DecodedDescriptor> {
+    if index >= gdtr.entry_count() {
+        return None;
+    }
+
+    let raw = unsafe {
+        let table = gdtr.base as usize as *const SegmentDescriptor;
+        ptr::read_unaligned(table.add(index))
+    };
+
+    let base = raw.base_low as u32 | (raw.base_middle as u32) << 16 | (raw.base_high as u32) << 24;
+    let flags = raw.granularity >> 4;
+    let mut limit = raw.limit_low as u32 | ((raw.granularity & 0x0F) as u32) << 16;
+    if flags & 0x8 != 0 {
+        limit = (limit << 12) | 0xFFF;
+    }
+
+    let access = raw.access;
+    let kind = if access == 0 && base == 0 && limit == 0 {
+        DescriptorKind::Null
+    } else if access & Access::CODE_OR_DATA == 0 {
+        match access & 0x0F {
+            SYSTEM_TYPE_LDT => DescriptorKind::Ldt,
+            SYSTEM_TYPE_TSS_AVAILABLE_32 => DescriptorKind::Tss { busy: false },
+            SYSTEM_TYPE_TSS_BUSY_32 => DescriptorKind::Tss { busy: true },
+            other => DescriptorKind::System(other),
+        }
+    } else if access & Access::EXECUTABLE != 0 {
+        DescriptorKind::Code {
+            readable: access & Access::READ_WRITE != 0,
+            conforming: access & Access::DIRECTION_CONFORMING != 0,
+        }
+    } else {
+        DescriptorKind::Data {
+            writable: access & Access::READ_WRITE != 0,
+            expand_down: access & Access::DIRECTION_CONFORMING != 0,
+        }
+    };
+
+    Some(DecodedDescriptor {
+        index,
+        selector: (index * size_of::<SegmentDescriptor>()) as u16,
+        base,
+        limit,
+        dpl: (access >> 5) & 0x03,
+        present: access & Access::PRESENT != 0,
+        kind,
+        access,
+        flags,
+    })
+}
+
 impl SegmentDescriptor {
-    const fn new(base: u32, limit: u32, access: u8) -> Self {
+    const fn new(base: u32, limit: u32, access: Access, flags: Granularity) -> Self {
         Self {
             limit_low: (limit & 0xFFFF) as u16,
             base_low: (base & 0xFFFF) as u16,
             base_middle: ((base >> 16) & 0xFF) as u8,
-            access,
-            granularity: ((limit >> 16) & 0x0F) as u8 | 0xC0,
+            access: access.bits(),
+            granularity: ((limit >> 16) & 0x0F) as u8 | flags.bits(),
             base_high: ((base >> 24) & 0xFF) as u8,
         }
     }
@@ -90,4 +407,39 @@ impl SegmentDescriptor {
             base_high: 0,
         }
     }
-}
\ No newline at end of file
+}
+
+impl TaskStateSegment {
+    const fn new() -> Self {
+        Self {
+            prev_task_link: 0,
+            esp0: 0,
+            ss0: KERNEL_DATA_SELECTOR as u32,
+            esp1: 0,
+            ss1: 0,
+            esp2: 0,
+            ss2: 0,
+            cr3: 0,
+            eip: 0,
+            eflags: 0,
+            eax: 0,
+            ecx: 0,
+            edx: 0,
+            ebx: 0,
+            esp: 0,
+            ebp: 0,
+            esi: 0,
+            edi: 0,
+            es: 0,
+            cs: 0,
+            ss: 0,
+            ds: 0,
+            fs: 0,
+            gs: 0,
+            ldtr: 0,
+            trap: 0,
+            // No I/O permission bitmap: the offset points past the end of the segment
+            iomap_base: size_of::<TaskStateSegment>() as u16,
+        }
+    }
+}
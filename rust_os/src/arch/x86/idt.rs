@@ -0,0 +1,96 @@
+use core::arch::asm;
+use core::mem::size_of;
+use super::exceptions;
+use super::gdt::KERNEL_CODE_SELECTOR;
+
+const IDT_ENTRIES: usize = 256;
+
+const GATE_PRESENT: u8 = 0x80;
+const GATE_INTERRUPT_32: u8 = 0x0E;
+
+#[repr(C, packed)]
+struct IdtDescriptor {
+    limit: u16,
+    base: u32,
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+struct GateDescriptor {
+    offset_low: u16,
+    selector: u16,
+    zero: u8,
+    type_attr: u8,
+    offset_high: u16,
+}
+
+// Pushed by the CPU before entering the handler. Ring 3 interrupts also push ESP and SS,
+// which are not read for now since the kernel only runs in ring 0.
+#[repr(C)]
+#[derive(Debug)]
+pub struct InterruptStackFrame {
+    pub eip: u32,
+    pub cs: u32,
+    pub eflags: u32,
+}
+
+pub type HandlerFunc = extern "x86-interrupt" fn(InterruptStackFrame);
+pub type HandlerFuncWithErrCode = extern "x86-interrupt" fn(InterruptStackFrame, u32);
+
+static mut IDT: [GateDescriptor; IDT_ENTRIES] = [GateDescriptor::missing(); IDT_ENTRIES];
+
+pub fn init_idt() {
+    exceptions::install_exception_handlers();
+
+    let idtr = IdtDescriptor {
+        #[allow(clippy::cast_possible_truncation)]
+        limit: (size_of::<[GateDescriptor; IDT_ENTRIES]>() - 1) as u16,
+        base: (&raw const IDT) as usize as u32,
+    };
+
+    unsafe {
+        asm!(
+            "lidt [{}]",
+            in(reg) &raw const idtr,
+            options(nostack, preserves_flags)
+        );
+    }
+}
+
+pub fn set_handler(vector: u8, handler: HandlerFunc) {
+    set_gate(vector, handler as usize as u32);
+}
+
+pub fn set_handler_with_err_code(vector: u8, handler: HandlerFuncWithErrCode) {
+    set_gate(vector, handler as usize as u32);
+}
+
+fn set_gate(vector: u8, offset: u32) {
+    let gate = GateDescriptor::new(offset, KERNEL_CODE_SELECTOR, GATE_PRESENT | GATE_INTERRUPT_32);
+    unsafe {
+        let idt = &raw mut IDT;
+        (*idt)[vector as usize] = gate;
+    }
+}
+
+impl GateDescriptor {
+    const fn new(offset: u32, selector: u16, type_attr: u8) -> Self {
+        Self {
+            offset_low: (offset & 0xFFFF) as u16,
+            selector,
+            zero: 0,
+            type_attr,
+            offset_high: ((offset >> 16) & 0xFFFF) as u16,
+        }
+    }
+
+    const fn missing() -> Self {
+        Self {
+            offset_low: 0,
+            selector: 0,
+            zero: 0,
+            type_attr: 0,
+            offset_high: 0,
+        }
+    }
+}
@@ -1,2 +1,9 @@
 pub mod port;
-pub mod gdt;
\ No newline at end of file
+pub mod gdt;
+pub mod cpu;
+pub mod idt;
+pub mod exceptions;
+pub mod pic;
+pub mod stack;
+pub mod paging;
+pub mod page_fault;
\ No newline at end of file
@@ -0,0 +1,182 @@
+use core::fmt;
+use super::cpu::{read_cr2, without_interrupts};
+use super::idt::InterruptStackFrame;
+use super::paging::{self, PAGE_SIZE, PAGE_TABLES_BASE};
+use super::stack::kernel_stack_bounds;
+use crate::memory::frame::kernel_image_bounds;
+use crate::memory::heap::{HEAP_MAX_SIZE, HEAP_START};
+use crate::memory::slab::{SLAB_END, SLAB_START};
+use crate::memory::vmalloc::{VMALLOC_END, VMALLOC_START};
+use crate::screen::screen::VGA_BUFFER_ADDRESS;
+
+#[derive(Clone, Copy, Debug)]
+pub struct PageFaultErrorCode(u32);
+
+impl PageFaultErrorCode {
+    const PRESENT: u32 = 1 << 0;
+    const WRITE: u32 = 1 << 1;
+    const USER: u32 = 1 << 2;
+    const RESERVED_BIT: u32 = 1 << 3;
+    const INSTRUCTION_FETCH: u32 = 1 << 4;
+
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    // Clear means the page was not mapped, set means its protection was violated
+    pub fn present(&self) -> bool {
+        self.0 & Self::PRESENT != 0
+    }
+
+    pub fn write(&self) -> bool {
+        self.0 & Self::WRITE != 0
+    }
+
+    pub fn user(&self) -> bool {
+        self.0 & Self::USER != 0
+    }
+
+    // RSVD: a reserved bit was set in a paging structure, the tables themselves are broken
+    pub fn reserved_bit(&self) -> bool {
+        self.0 & Self::RESERVED_BIT != 0
+    }
+
+    pub fn instruction_fetch(&self) -> bool {
+        self.0 & Self::INSTRUCTION_FETCH != 0
+    }
+}
+
+impl fmt::Display for PageFaultErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cause = if self.present() { "protection violation" } else { "page not present" };
+        let access = if self.instruction_fetch() {
+            "instruction fetch"
+        } else if self.write() {
+            "write"
+        } else {
+            "read"
+        };
+        let mode = if self.user() { "user" } else { "kernel" };
+
+        write!(f, "{}, {} in {} mode", cause, access, mode)?;
+        if self.reserved_bit() {
+            write!(f, ", reserved bit set")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PageFaultInfo {
+    pub address: u32,
+    pub error_code: PageFaultErrorCode,
+    pub eip: u32,
+}
+
+impl PageFaultInfo {
+    pub fn page(&self) -> u32 {
+        self.address & !(PAGE_SIZE - 1)
+    }
+}
+
+// Returns true once the fault is dealt with and the faulting instruction can be retried
+pub type PageFaultHook = fn(&PageFaultInfo) -> bool;
+
+static mut PAGE_FAULT_HOOK: Option<PageFaultHook> = None;
+
+pub fn register_page_fault_hook(hook: PageFaultHook) -> bool {
+    without_interrupts(|| unsafe {
+        let slot = &raw mut PAGE_FAULT_HOOK;
+        if (*slot).is_some() {
+            false
+        } else {
+            *slot = Some(hook);
+            true
+        }
+    })
+}
+
+pub fn unregister_page_fault_hook() {
+    without_interrupts(|| unsafe {
+        let slot = &raw mut PAGE_FAULT_HOOK;
+        *slot = None;
+    });
+}
+
+// Names the part of the address space `addr` belongs to, whether or not it is mapped
+pub fn address_region(addr: u32) -> Option<&'static str> {
+    let addr = addr as usize;
+    let (stack_bottom, stack_top) = kernel_stack_bounds();
+    let (kernel_start, kernel_end) = kernel_image_bounds();
+
+    let regions = [
+        (0, PAGE_SIZE as usize, "null page"),
+        (stack_bottom as usize, stack_top as usize, "kernel stack"),
+        (kernel_start as usize, kernel_end as usize, "kernel image"),
+        (VGA_BUFFER_ADDRESS, VGA_BUFFER_ADDRESS + PAGE_SIZE as usize, "VGA buffer"),
+        (HEAP_START, HEAP_START + HEAP_MAX_SIZE, "kernel heap"),
+        (VMALLOC_START, VMALLOC_END, "vmalloc zone"),
+        (SLAB_START, SLAB_END, "slab pages"),
+        (PAGE_TABLES_BASE as usize, usize::MAX, "recursive page tables"),
+    ];
+    regions
+        .iter()
+        .find(|&&(start, end, _)| addr >= start && addr < end)
+        .map(|&(_, _, name)| name)
+}
+
+// What the page tables say about the faulting address, read without the paging lock
+struct MappingReport(u32);
+
+impl fmt::Display for MappingReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match paging::walk_unlocked(self.0) {
+            Some(walk) if !walk.directory_entry.is_present() => {
+                write!(f, "no page table covers this address")
+            }
+            Some(walk) => match walk.table_entry {
+                Some(entry) if entry.is_present() => write!(
+                    f,
+                    "mapped to frame {:#010x}, flags {}",
+                    entry.frame().start_address(),
+                    entry.flags()
+                ),
+                _ => write!(f, "page is not mapped"),
+            },
+            None => write!(f, "paging is disabled"),
+        }
+    }
+}
+
+pub extern "x86-interrupt" fn page_fault_handler(frame: InterruptStackFrame, error_code: u32) {
+    let info = PageFaultInfo {
+        address: read_cr2(),
+        error_code: PageFaultErrorCode(error_code),
+        eip: frame.eip,
+    };
+
+    // Corrupted tables cannot be fixed up by a hook, everything else gets a chance first
+    if !info.error_code.reserved_bit() {
+        let hook = unsafe {
+            let slot = &raw const PAGE_FAULT_HOOK;
+            *slot
+        };
+        if let Some(hook) = hook {
+            if hook(&info) {
+                return;
+            }
+        }
+    }
+
+    // The details go through the panic screen, which draws without the screen lock that
+    // the faulting code may have been holding
+    panic!(
+        "PAGE FAULT at {:#010x} ({}), EIP={:#010x}\n  error code {:#x}: {}\n  {}",
+        info.address,
+        address_region(info.address).unwrap_or("outside any kernel region"),
+        info.eip,
+        info.error_code.bits(),
+        info.error_code,
+        MappingReport(info.address)
+    );
+}
@@ -0,0 +1,392 @@
+use core::arch::asm;
+use core::fmt::{self, Write};
+use core::ops::{BitOr, BitOrAssign};
+use core::ptr;
+use super::cpu::{self, without_interrupts};
+use crate::kspin_lock::KSpinLock;
+use crate::memory::frame::{self, PhysFrame};
+use crate::printk;
+use crate::memory::address::{virt_to_phys, BOOT_MAPPING_SIZE, KERNEL_OFFSET};
+use crate::screen::screen::VGA_BUFFER_ADDRESS;
+
+pub const PAGE_SIZE: u32 = 4096;
+const ENTRIES_PER_TABLE: usize = 1024;
+
+// The last directory entry points back at the directory, so once paging is on every page
+// table shows up at PAGE_TABLES_BASE + index * 4 KiB and the directory itself at 0xFFFFF000
+const RECURSIVE_INDEX: usize = ENTRIES_PER_TABLE - 1;
+pub const PAGE_TABLES_BASE: u32 = 0xFFC0_0000;
+const PAGE_DIRECTORY_ADDRESS: u32 = 0xFFFF_F000;
+
+const CR0_PAGING: u32 = 1 << 31;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PageFlags(u32);
+
+impl PageFlags {
+    pub const PRESENT: Self = Self(1 << 0);
+    pub const WRITABLE: Self = Self(1 << 1);
+    pub const USER: Self = Self(1 << 2);
+    pub const WRITE_THROUGH: Self = Self(1 << 3);
+    pub const CACHE_DISABLE: Self = Self(1 << 4);
+    pub const ACCESSED: Self = Self(1 << 5);
+    pub const DIRTY: Self = Self(1 << 6);
+    pub const GLOBAL: Self = Self(1 << 8);
+
+    const MASK: u32 = 0xFFF;
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn from_bits_truncate(bits: u32) -> Self {
+        Self(bits & Self::MASK)
+    }
+
+    pub const fn bits(&self) -> u32 {
+        self.0
+    }
+
+    pub fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: Self) {
+        self.0 &= !other.0;
+    }
+}
+
+impl BitOr for PageFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for PageFlags {
+    fn bitor_assign(&mut self, other: Self) {
+        self.insert(other);
+    }
+}
+
+// One letter per flag, '-' when it is clear: P W U T C A D G
+impl fmt::Display for PageFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let letters = [
+            (Self::PRESENT, 'P'),
+            (Self::WRITABLE, 'W'),
+            (Self::USER, 'U'),
+            (Self::WRITE_THROUGH, 'T'),
+            (Self::CACHE_DISABLE, 'C'),
+            (Self::ACCESSED, 'A'),
+            (Self::DIRTY, 'D'),
+            (Self::GLOBAL, 'G'),
+        ];
+        for (flag, letter) in letters {
+            f.write_char(if self.contains(flag) { letter } else { '-' })?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+#[repr(transparent)]
+pub struct PageTableEntry(u32);
+
+impl PageTableEntry {
+    pub fn is_present(&self) -> bool {
+        self.flags().contains(PageFlags::PRESENT)
+    }
+
+    pub fn frame(&self) -> PhysFrame {
+        PhysFrame::containing_address(self.0)
+    }
+
+    pub fn flags(&self) -> PageFlags {
+        PageFlags::from_bits_truncate(self.0)
+    }
+
+    fn set(&mut self, frame: PhysFrame, flags: PageFlags) {
+        self.0 = frame.start_address() | flags.bits();
+    }
+
+    fn clear(&mut self) {
+        self.0 = 0;
+    }
+}
+
+#[repr(C, align(4096))]
+struct PageTable {
+    entries: [PageTableEntry; ENTRIES_PER_TABLE],
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum PagingError {
+    Unaligned(u32),
+    AlreadyMapped(u32),
+    NotMapped(u32),
+    ReservedAddress(u32),
+    OutOfFrames,
+    NotInitialized,
+}
+
+impl fmt::Display for PagingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PagingError::Unaligned(addr) => write!(f, "{:#010x} is not page aligned", addr),
+            PagingError::AlreadyMapped(addr) => write!(f, "{:#010x} is already mapped", addr),
+            PagingError::NotMapped(addr) => write!(f, "{:#010x} is not mapped", addr),
+            PagingError::ReservedAddress(addr) => {
+                write!(f, "{:#010x} belongs to the recursive page table mapping", addr)
+            }
+            PagingError::OutOfFrames => write!(f, "out of physical frames for page tables"),
+            PagingError::NotInitialized => write!(f, "no page directory has been set up"),
+        }
+    }
+}
+
+struct Paging {
+    // Physical address of the page directory, 0 until init_paging has taken over the boot one
+    directory: u32,
+}
+
+static PAGING: KSpinLock<Paging> = KSpinLock::new(Paging { directory: 0 });
+
+// Set up by boot.asm, recursive entry included
+extern "C" {
+    static boot_page_directory: u8;
+}
+
+fn directory_index(virt: u32) -> usize {
+    (virt >> 22) as usize
+}
+
+fn table_index(virt: u32) -> usize {
+    ((virt >> 12) & 0x3FF) as usize
+}
+
+impl Paging {
+    fn directory(&mut self) -> &mut PageTable {
+        unsafe { &mut *(PAGE_DIRECTORY_ADDRESS as usize as *mut PageTable) }
+    }
+
+    fn table(&mut self, index: usize) -> &mut PageTable {
+        let addr = PAGE_TABLES_BASE + index as u32 * PAGE_SIZE;
+        unsafe { &mut *(addr as usize as *mut PageTable) }
+    }
+
+    fn entry(&mut self, virt: u32) -> Option<&mut PageTableEntry> {
+        let index = directory_index(virt);
+        if self.directory == 0 || !self.directory().entries[index].is_present() {
+            return None;
+        }
+        Some(&mut self.table(index).entries[table_index(virt)])
+    }
+
+    fn entry_or_create(&mut self, virt: u32) -> Result<&mut PageTableEntry, PagingError> {
+        if self.directory == 0 {
+            return Err(PagingError::NotInitialized);
+        }
+
+        let index = directory_index(virt);
+        if !self.directory().entries[index].is_present() {
+            let frame = frame::alloc_frame().ok_or(PagingError::OutOfFrames)?;
+            // Access rights are enforced per page, the directory entry allows everything
+            self.directory().entries[index].set(
+                frame,
+                PageFlags::PRESENT | PageFlags::WRITABLE | PageFlags::USER,
+            );
+            flush_tlb(PAGE_TABLES_BASE + index as u32 * PAGE_SIZE);
+            unsafe {
+                ptr::write_bytes(self.table(index) as *mut PageTable, 0, 1);
+            }
+        }
+        Ok(&mut self.table(index).entries[table_index(virt)])
+    }
+
+    fn map(&mut self, virt: u32, frame: PhysFrame, flags: PageFlags) -> Result<(), PagingError> {
+        check_address(virt)?;
+
+        let entry = self.entry_or_create(virt)?;
+        if entry.is_present() {
+            return Err(PagingError::AlreadyMapped(virt));
+        }
+        entry.set(frame, flags | PageFlags::PRESENT);
+        flush_tlb(virt);
+        Ok(())
+    }
+
+    fn unmap(&mut self, virt: u32) -> Result<PhysFrame, PagingError> {
+        check_address(virt)?;
+
+        let entry = match self.entry(virt) {
+            Some(entry) if entry.is_present() => entry,
+            _ => return Err(PagingError::NotMapped(virt)),
+        };
+        let frame = entry.frame();
+        entry.clear();
+        flush_tlb(virt);
+        Ok(frame)
+    }
+
+    fn set_flags(&mut self, virt: u32, flags: PageFlags) -> Result<(), PagingError> {
+        check_address(virt)?;
+
+        let entry = match self.entry(virt) {
+            Some(entry) if entry.is_present() => entry,
+            _ => return Err(PagingError::NotMapped(virt)),
+        };
+        let frame = entry.frame();
+        entry.set(frame, flags | PageFlags::PRESENT);
+        flush_tlb(virt);
+        Ok(())
+    }
+}
+
+fn check_address(virt: u32) -> Result<(), PagingError> {
+    if virt & (PAGE_SIZE - 1) != 0 {
+        return Err(PagingError::Unaligned(virt));
+    }
+    if directory_index(virt) == RECURSIVE_INDEX {
+        return Err(PagingError::ReservedAddress(virt));
+    }
+    Ok(())
+}
+
+// boot.asm already runs the kernel in the higher half, with the whole first 4 MiB mapped at
+// KERNEL_OFFSET. Its directory is kept, everything in that window except the kernel image
+// and the VGA buffer is unmapped so those frames are only reachable through the allocator.
+pub fn init_paging() -> Result<(), PagingError> {
+    let (kernel_start, kernel_end) = frame::kernel_image_bounds();
+    let vga = VGA_BUFFER_ADDRESS as u32;
+    let window_start = KERNEL_OFFSET as u32;
+    let window_end = (KERNEL_OFFSET + BOOT_MAPPING_SIZE) as u32;
+
+    let directory = without_interrupts(|| {
+        let mut paging = PAGING.lock();
+        paging.directory = virt_to_phys((&raw const boot_page_directory) as usize);
+
+        let mut page = window_start;
+        while page < window_end {
+            let in_kernel = page >= kernel_start && page < kernel_end;
+            if !in_kernel && page != vga {
+                paging.unmap(page)?;
+            }
+            page += PAGE_SIZE;
+        }
+        paging.set_flags(vga, PageFlags::WRITABLE | PageFlags::CACHE_DISABLE)?;
+        Ok(paging.directory)
+    })?;
+
+    printk!(
+        LogLevel::Info,
+        "Paging: kernel at {:#010x}-{:#010x}, VGA at {:#010x}, page directory at {:#010x}\n",
+        kernel_start,
+        kernel_end,
+        vga,
+        directory
+    );
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PageWalk {
+    pub directory_entry: PageTableEntry,
+    pub table_entry: Option<PageTableEntry>,
+}
+
+// Reads the live tables through the recursive mapping without taking the lock, for fault
+// handlers that may have interrupted its holder. None while paging is off.
+pub fn walk_unlocked(virt: u32) -> Option<PageWalk> {
+    if cpu::read_cr0() & CR0_PAGING == 0 {
+        return None;
+    }
+
+    let index = directory_index(virt);
+    unsafe {
+        let directory = PAGE_DIRECTORY_ADDRESS as usize as *const PageTable;
+        let directory_entry = ptr::read_volatile(&raw const (*directory).entries[index]);
+        let table_entry = directory_entry.is_present().then(|| {
+            let table = (PAGE_TABLES_BASE + index as u32 * PAGE_SIZE) as usize as *const PageTable;
+            ptr::read_volatile(&raw const (*table).entries[table_index(virt)])
+        });
+        Some(PageWalk { directory_entry, table_entry })
+    }
+}
+
+pub fn is_paging_enabled() -> bool {
+    cpu::read_cr0() & CR0_PAGING != 0
+}
+
+pub fn map_page(virt: u32, frame: PhysFrame, flags: PageFlags) -> Result<(), PagingError> {
+    without_interrupts(|| PAGING.lock().map(virt, frame, flags))
+}
+
+// Leaves the frame allocated, it is returned so the caller can decide what to do with it
+pub fn unmap_page(virt: u32) -> Result<PhysFrame, PagingError> {
+    without_interrupts(|| PAGING.lock().unmap(virt))
+}
+
+pub fn translate(virt: u32) -> Option<u32> {
+    without_interrupts(|| {
+        let mut paging = PAGING.lock();
+        match paging.entry(virt) {
+            Some(entry) if entry.is_present() => {
+                Some(entry.frame().start_address() | (virt & (PAGE_SIZE - 1)))
+            }
+            _ => None,
+        }
+    })
+}
+
+pub fn page_flags(virt: u32) -> Option<PageFlags> {
+    without_interrupts(|| {
+        let mut paging = PAGING.lock();
+        paging.entry(virt & !(PAGE_SIZE - 1)).map(|entry| entry.flags())
+    })
+}
+
+// True when every page touched by [start, start + len) is present, and writable if asked
+pub fn is_range_mapped(start: u32, len: u32, writable: bool) -> bool {
+    if len == 0 {
+        return true;
+    }
+    let Some(last) = start.checked_add(len - 1) else {
+        return false;
+    };
+
+    let last_page = last & !(PAGE_SIZE - 1);
+    let mut page = start & !(PAGE_SIZE - 1);
+    loop {
+        match page_flags(page) {
+            Some(flags)
+                if flags.contains(PageFlags::PRESENT)
+                    && (!writable || flags.contains(PageFlags::WRITABLE)) => {}
+            _ => return false,
+        }
+        if page == last_page {
+            return true;
+        }
+        page += PAGE_SIZE;
+    }
+}
+
+// PRESENT is always kept, use unmap_page to drop a mapping
+pub fn set_page_flags(virt: u32, flags: PageFlags) -> Result<(), PagingError> {
+    without_interrupts(|| PAGING.lock().set_flags(virt, flags))
+}
+
+pub fn flush_tlb(virt: u32) {
+    unsafe {
+        asm!("invlpg [{}]", in(reg) virt as usize, options(nostack, preserves_flags));
+    }
+}
+
+pub fn flush_tlb_all() {
+    cpu::write_cr3(cpu::read_cr3());
+}
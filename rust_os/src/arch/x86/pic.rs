@@ -0,0 +1,215 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+use super::cpu::without_interrupts;
+use super::idt::{self, InterruptStackFrame};
+use super::port::{inb, io_wait, outb};
+
+const PIC1_COMMAND: u16 = 0x20;
+const PIC1_DATA: u16 = 0x21;
+const PIC2_COMMAND: u16 = 0xA0;
+const PIC2_DATA: u16 = 0xA1;
+
+pub const PIC1_OFFSET: u8 = 0x20;
+pub const PIC2_OFFSET: u8 = 0x28;
+pub const IRQ_LINES: usize = 16;
+
+const ICW1_ICW4: u8 = 0x01;
+const ICW1_INIT: u8 = 0x10;
+const ICW4_8086: u8 = 0x01;
+const OCW3_READ_ISR: u8 = 0x0B;
+const PIC_EOI: u8 = 0x20;
+
+// The slave PIC is wired to IRQ2 of the master
+const CASCADE_IRQ: u8 = 2;
+
+pub type IrqHandler = fn();
+
+static mut IRQ_HANDLERS: [Option<IrqHandler>; IRQ_LINES] = [None; IRQ_LINES];
+static SPURIOUS_IRQS: AtomicU32 = AtomicU32::new(0);
+
+macro_rules! irq_handler {
+    ($name:ident, $irq:expr) => {
+        extern "x86-interrupt" fn $name(_frame: InterruptStackFrame) {
+            dispatch_irq($irq);
+        }
+    };
+}
+
+irq_handler!(irq0, 0);
+irq_handler!(irq1, 1);
+irq_handler!(irq2, 2);
+irq_handler!(irq3, 3);
+irq_handler!(irq4, 4);
+irq_handler!(irq5, 5);
+irq_handler!(irq6, 6);
+irq_handler!(irq7, 7);
+irq_handler!(irq8, 8);
+irq_handler!(irq9, 9);
+irq_handler!(irq10, 10);
+irq_handler!(irq11, 11);
+irq_handler!(irq12, 12);
+irq_handler!(irq13, 13);
+irq_handler!(irq14, 14);
+irq_handler!(irq15, 15);
+
+pub fn init_pic() {
+    unsafe {
+        // ICW1: start the initialization sequence, ICW4 will follow
+        outb(PIC1_COMMAND, ICW1_INIT | ICW1_ICW4);
+        io_wait();
+        outb(PIC2_COMMAND, ICW1_INIT | ICW1_ICW4);
+        io_wait();
+
+        // ICW2: vector offsets, out of the way of the CPU exceptions
+        outb(PIC1_DATA, PIC1_OFFSET);
+        io_wait();
+        outb(PIC2_DATA, PIC2_OFFSET);
+        io_wait();
+
+        // ICW3: tell the master where the slave is, and the slave its cascade identity
+        outb(PIC1_DATA, 1 << CASCADE_IRQ);
+        io_wait();
+        outb(PIC2_DATA, CASCADE_IRQ);
+        io_wait();
+
+        // ICW4: 8086 mode
+        outb(PIC1_DATA, ICW4_8086);
+        io_wait();
+        outb(PIC2_DATA, ICW4_8086);
+        io_wait();
+
+        // Every line stays masked until a driver registers for it
+        outb(PIC1_DATA, !(1 << CASCADE_IRQ));
+        outb(PIC2_DATA, 0xFF);
+    }
+
+    let handlers: [idt::HandlerFunc; IRQ_LINES] = [
+        irq0, irq1, irq2, irq3, irq4, irq5, irq6, irq7,
+        irq8, irq9, irq10, irq11, irq12, irq13, irq14, irq15,
+    ];
+    for (irq, handler) in handlers.iter().enumerate() {
+        idt::set_handler(PIC1_OFFSET + irq as u8, *handler);
+    }
+}
+
+pub fn register_irq_handler(irq: u8, handler: IrqHandler) -> bool {
+    if irq as usize >= IRQ_LINES || irq == CASCADE_IRQ {
+        return false;
+    }
+
+    let registered = without_interrupts(|| unsafe {
+        let handlers = &raw mut IRQ_HANDLERS;
+        if (*handlers)[irq as usize].is_some() {
+            false
+        } else {
+            (*handlers)[irq as usize] = Some(handler);
+            true
+        }
+    });
+
+    if registered {
+        unmask_irq(irq);
+    }
+    registered
+}
+
+pub fn unregister_irq_handler(irq: u8) {
+    if irq as usize >= IRQ_LINES || irq == CASCADE_IRQ {
+        return;
+    }
+
+    mask_irq(irq);
+    without_interrupts(|| unsafe {
+        let handlers = &raw mut IRQ_HANDLERS;
+        (*handlers)[irq as usize] = None;
+    });
+}
+
+// The mask is read, changed and written back, an IRQ handler changing it in between
+// would have its update lost
+pub fn mask_irq(irq: u8) {
+    if irq as usize >= IRQ_LINES {
+        return;
+    }
+
+    let (port, line) = data_port_and_line(irq);
+    without_interrupts(|| unsafe {
+        let mask = inb(port) | (1 << line);
+        outb(port, mask);
+    });
+}
+
+pub fn unmask_irq(irq: u8) {
+    if irq as usize >= IRQ_LINES {
+        return;
+    }
+
+    let (port, line) = data_port_and_line(irq);
+    without_interrupts(|| unsafe {
+        let mask = inb(port) & !(1 << line);
+        outb(port, mask);
+    });
+}
+
+pub fn send_eoi(irq: u8) {
+    unsafe {
+        if irq >= 8 {
+            outb(PIC2_COMMAND, PIC_EOI);
+        }
+        outb(PIC1_COMMAND, PIC_EOI);
+    }
+}
+
+pub fn spurious_irq_count() -> u32 {
+    SPURIOUS_IRQS.load(Ordering::Relaxed)
+}
+
+fn data_port_and_line(irq: u8) -> (u16, u8) {
+    if irq < 8 {
+        (PIC1_DATA, irq)
+    } else {
+        (PIC2_DATA, irq - 8)
+    }
+}
+
+// In-service register of both PICs, slave in the high byte
+fn read_isr() -> u16 {
+    unsafe {
+        outb(PIC1_COMMAND, OCW3_READ_ISR);
+        outb(PIC2_COMMAND, OCW3_READ_ISR);
+        ((inb(PIC2_COMMAND) as u16) << 8) | inb(PIC1_COMMAND) as u16
+    }
+}
+
+// A spurious IRQ7/IRQ15 is raised on the lowest priority line without the matching ISR bit.
+// It must not be acknowledged, except that the master did see a real cascade interrupt
+// for a spurious IRQ15 and still expects its EOI.
+fn is_spurious(irq: u8) -> bool {
+    match irq {
+        7 => read_isr() & (1 << 7) == 0,
+        15 if read_isr() & (1 << 15) == 0 => {
+            unsafe {
+                outb(PIC1_COMMAND, PIC_EOI);
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
+fn dispatch_irq(irq: u8) {
+    if is_spurious(irq) {
+        SPURIOUS_IRQS.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+
+    let handler = unsafe {
+        let handlers = &raw const IRQ_HANDLERS;
+        (*handlers)[irq as usize]
+    };
+
+    if let Some(handler) = handler {
+        handler();
+    }
+
+    send_eoi(irq);
+}
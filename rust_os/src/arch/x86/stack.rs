@@ -0,0 +1,132 @@
+use core::fmt::{self, Write};
+use core::ptr;
+use super::cpu::{read_ebp, read_esp};
+
+const BYTES_PER_LINE: u32 = 16;
+// Dump lines per page, leaves room for the header, the frame markers and the prompt
+pub const STACK_PAGE_LINES: u32 = 16;
+const MAX_FRAMES: usize = 32;
+
+// Defined in boot/boot.asm
+extern "C" {
+    static stack_bottom: u8;
+    static stack_top: u8;
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct StackFrame {
+    // Address of the saved EBP slot, the return address sits just above it
+    pub frame_pointer: u32,
+    pub return_address: u32,
+}
+
+pub fn kernel_stack_bounds() -> (u32, u32) {
+    (
+        (&raw const stack_bottom) as usize as u32,
+        (&raw const stack_top) as usize as u32,
+    )
+}
+
+// Follows the saved EBP chain from `ebp` until it leaves the kernel stack or reaches the
+// null frame pointer pushed by _start. Returns the number of frames stored in `frames`.
+pub fn walk_frames(ebp: u32, frames: &mut [StackFrame]) -> usize {
+    let (bottom, top) = kernel_stack_bounds();
+    let mut frame_pointer = ebp;
+    let mut count = 0;
+
+    while count < frames.len()
+        && frame_pointer >= bottom
+        && frame_pointer + 8 <= top
+        && frame_pointer & 0x3 == 0
+    {
+        let (saved_ebp, return_address) = unsafe {
+            (
+                ptr::read_volatile(frame_pointer as usize as *const u32),
+                ptr::read_volatile((frame_pointer + 4) as usize as *const u32),
+            )
+        };
+
+        frames[count] = StackFrame { frame_pointer, return_address };
+        count += 1;
+
+        // The stack grows down, so each caller frame must sit strictly higher
+        if saved_ebp <= frame_pointer {
+            break;
+        }
+        frame_pointer = saved_ebp;
+    }
+    count
+}
+
+// Dumps the stack from ESP up to stack_top, `page` selects which STACK_PAGE_LINES lines of
+// the dump are shown. A page that is not the last one says how to get the next.
+pub fn dump_stack<W: Write>(out: &mut W, page: u32) -> fmt::Result {
+    let esp = read_esp();
+    let ebp = read_ebp();
+    let (bottom, top) = kernel_stack_bounds();
+
+    writeln!(
+        out,
+        "Kernel stack {:#010x}-{:#010x}  ESP={:#010x} EBP={:#010x}",
+        bottom, top, esp, ebp
+    )?;
+
+    if esp < bottom || esp > top {
+        return writeln!(out, "ESP is outside of the kernel stack, nothing to dump.");
+    }
+    writeln!(out, "{} of {} bytes in use", top - esp, top - bottom)?;
+
+    let mut frames = [StackFrame { frame_pointer: 0, return_address: 0 }; MAX_FRAMES];
+    let frame_count = walk_frames(ebp, &mut frames);
+    let frames = &frames[..frame_count];
+
+    let first_line = esp & !(BYTES_PER_LINE - 1);
+    let total_lines = (top - first_line).div_ceil(BYTES_PER_LINE);
+    let pages = total_lines.div_ceil(STACK_PAGE_LINES);
+    if page >= pages {
+        return writeln!(out, "Page {} is past the end, the dump has {} pages.", page, pages);
+    }
+
+    let start = first_line + page * STACK_PAGE_LINES * BYTES_PER_LINE;
+    let end = top.min(start + STACK_PAGE_LINES * BYTES_PER_LINE);
+
+    let mut line = start;
+    while line < end {
+        for (depth, frame) in frames.iter().enumerate() {
+            if frame.frame_pointer >= line && frame.frame_pointer < line + BYTES_PER_LINE {
+                writeln!(
+                    out,
+                    "-- frame #{} at {:#010x}, return to {:#010x} --",
+                    depth, frame.frame_pointer, frame.return_address
+                )?;
+            }
+        }
+
+        write!(out, "{:#010x}:", line)?;
+        let mut word = line;
+        while word < line + BYTES_PER_LINE {
+            if word < esp || word >= top {
+                write!(out, "         ")?;
+            } else {
+                let value = unsafe { ptr::read_volatile(word as usize as *const u32) };
+                write!(out, " {:08x}", value)?;
+            }
+            word += 4;
+        }
+        writeln!(out)?;
+
+        line += BYTES_PER_LINE;
+    }
+
+    if end < top {
+        writeln!(
+            out,
+            "-- page {}/{}, {} more bytes up to stack_top: 'stack {}' for the next --",
+            page + 1,
+            pages,
+            top - end,
+            page + 1
+        )?;
+    }
+    Ok(())
+}
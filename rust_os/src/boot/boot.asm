@@ -5,12 +5,17 @@
 %define MAGIC 0x1BADB002            ; magic number for multiboot1 multiboot2 is 0x36D76289. Let's the bootloaeder find the header
 %define CHECKSUM (-(MAGIC + FLAGS)) ; checksum for the multiboot header to prove that the header is valid
 
+; The kernel is linked at KERNEL_OFFSET + its physical address. Must match config/linker.ld and src/memory/address.rs
+%define KERNEL_OFFSET 0xC0000000
+%define PAGE_PRESENT_WRITABLE 0x003 ; page table entry flags: present | writable
+%define CR0_PAGING_WP 0x80010000    ; CR0.PG turns paging on, CR0.WP makes read-only pages apply to the kernel too
+
 ; External symbol for the main function
 extern kernel_main                  ; this is the main function of the kernel. It is defined in another file
 
 ; The multiboot header is a special structure that the bootloader uses to find the kernel and load it into memory
 ; The header is 32 bytes long and contains the following fields:
-section .multiboot                   ; new seciton in the binary contains the multiboot header information
+section .multiboot.data              ; new seciton in the binary contains the multiboot header information
 align 4                              ; ensure the header is aligned to 4 bytes. Required by the multiboot specification
 dd MAGIC                             ; places the magic number value into the header
 dd FLAGS                             ; places the flags value into the header
@@ -20,18 +25,73 @@ dd CHECKSUM                          ; Adds a checksum value that must make the
 ; The stack is defined in the .bss section, which is uninitialized data. The stack grows downwards, so we need to define the bottom of the stack first
 ; The stack is 16 KiB in size, which is enough for most applications. The stack pointer will be set to the top of the stack
 
+global stack_bottom         ; exported so the kernel can walk and dump its own stack
+global stack_top
+
+global boot_page_directory  ; kept as the kernel's page directory once paging.rs takes over
+
 section .bss                ; new section in the binary contains uninitialized data
+align 4096                  ; paging structures must be page aligned
+boot_page_directory:        ; 1024 entries, the first 4 MiB are mapped at 0 and at KERNEL_OFFSET
+    resb 4096
+boot_page_table:            ; maps physical 0-4 MiB, which holds the whole kernel image
+    resb 4096
+
 align 16                    ; ensure the stack is aligned to 16 bytes. This is required by the x86_64 ABI
 stack_bottom:               ; this is the bottom of the stack
     resb 16384              ; reserve 16 KiB of space for the stack. This is the size of the stack
 stack_top:                  ; this is the top of the stack
 ; The stack pointer will be set to the top of the stack
 
-section .text               ; new section in the binary contains code
+; _start runs with paging off at the physical load address, so every higher half symbol it
+; touches is taken minus KERNEL_OFFSET. EAX and EBX hold the multiboot values and are left alone.
+section .multiboot.text progbits alloc exec nowrite align=16 ; new section for the code that runs before paging
 global _start               ; make the _start label global so the linker can find it
 _start:                     ; this is the entry point of the kernel | tell the linker where the kernel starts
+    ; Fill the boot page table with the first 4 MiB of physical memory
+    mov edi, boot_page_table - KERNEL_OFFSET
+    mov edx, PAGE_PRESENT_WRITABLE
+    mov ecx, 1024
+.fill_table:
+    mov [edi], edx
+    add edx, 4096
+    add edi, 4
+    loop .fill_table
+
+    ; The same table is used at 0, so the next instructions still run once paging is on,
+    ; and at KERNEL_OFFSET where the kernel is linked. The last entry points back at the
+    ; directory so paging.rs can reach every page table at 0xFFC00000.
+    mov edi, boot_page_directory - KERNEL_OFFSET
+    mov edx, boot_page_table - KERNEL_OFFSET + PAGE_PRESENT_WRITABLE
+    mov [edi], edx
+    mov [edi + (KERNEL_OFFSET >> 22) * 4], edx
+    mov edx, boot_page_directory - KERNEL_OFFSET + PAGE_PRESENT_WRITABLE
+    mov [edi + 1023 * 4], edx
+
+    ; Turn paging on
+    mov cr3, edi
+    mov edx, cr0
+    or edx, CR0_PAGING_WP
+    mov cr0, edx
+
+    ; Absolute jump into the higher half
+    lea edx, [higher_half]
+    jmp edx
+
+section .text               ; new section in the binary contains code
+higher_half:
+    ; Nothing runs at the identity mapping anymore, drop it and flush the TLB
+    mov dword [boot_page_directory], 0
+    mov edx, cr3
+    mov cr3, edx
+
     ; Set up the stack pointer
     mov esp, $stack_top     ; set the stack pointer to the top of the stack
+    xor ebp, ebp            ; null frame pointer: marks the end of the frame chain for stack walks
+
+    ; Forward what GRUB left in the registers: kernel_main(magic, multiboot_info)
+    push ebx                ; second argument: physical address of the multiboot information structure
+    push eax                ; first argument: bootloader magic, 0x2BADB002 when loaded by a multiboot bootloader
 
     ; Call the main function
     call kernel_main        ; call the main function don't name it _clestart to avoid confusion with the _start label
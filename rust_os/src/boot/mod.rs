@@ -0,0 +1 @@
+pub mod multiboot;
\ No newline at end of file
@@ -0,0 +1,345 @@
+use core::fmt;
+use core::mem;
+use core::ptr;
+use crate::memory::address::{is_boot_mapped, phys_to_virt, BOOT_MAPPING_SIZE};
+use crate::printk;
+
+pub const BOOTLOADER_MAGIC: u32 = 0x2BAD_B002;
+
+pub const FLAG_MEMORY: u32 = 1 << 0;
+pub const FLAG_BOOT_DEVICE: u32 = 1 << 1;
+pub const FLAG_CMDLINE: u32 = 1 << 2;
+pub const FLAG_MODULES: u32 = 1 << 3;
+pub const FLAG_MEMORY_MAP: u32 = 1 << 6;
+pub const FLAG_BOOT_LOADER_NAME: u32 = 1 << 9;
+
+pub const MAX_MEMORY_REGIONS: usize = 32;
+const MAX_STRING_LEN: usize = 128;
+
+// Layout of the structure GRUB leaves in memory, see the Multiboot 0.6.96 specification
+#[repr(C)]
+struct RawBootInfo {
+    flags: u32,
+    mem_lower: u32,
+    mem_upper: u32,
+    boot_device: u32,
+    cmdline: u32,
+    mods_count: u32,
+    mods_addr: u32,
+    syms: [u32; 4],
+    mmap_length: u32,
+    mmap_addr: u32,
+    drives_length: u32,
+    drives_addr: u32,
+    config_table: u32,
+    boot_loader_name: u32,
+}
+
+// The `size` field does not count itself, entries are `size + 4` bytes apart
+#[repr(C, packed)]
+struct RawMemoryMapEntry {
+    size: u32,
+    base_addr: u64,
+    length: u64,
+    kind: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryRegionKind {
+    Available,
+    Reserved,
+    AcpiReclaimable,
+    AcpiNvs,
+    BadMemory,
+    Unknown(u32),
+}
+
+impl MemoryRegionKind {
+    fn from_raw(kind: u32) -> Self {
+        match kind {
+            1 => MemoryRegionKind::Available,
+            2 => MemoryRegionKind::Reserved,
+            3 => MemoryRegionKind::AcpiReclaimable,
+            4 => MemoryRegionKind::AcpiNvs,
+            5 => MemoryRegionKind::BadMemory,
+            other => MemoryRegionKind::Unknown(other),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MemoryRegionKind::Available => "available",
+            MemoryRegionKind::Reserved => "reserved",
+            MemoryRegionKind::AcpiReclaimable => "ACPI reclaimable",
+            MemoryRegionKind::AcpiNvs => "ACPI NVS",
+            MemoryRegionKind::BadMemory => "bad memory",
+            MemoryRegionKind::Unknown(_) => "unknown",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MemoryRegion {
+    pub base: u64,
+    pub length: u64,
+    pub kind: MemoryRegionKind,
+}
+
+impl MemoryRegion {
+    const fn empty() -> Self {
+        Self {
+            base: 0,
+            length: 0,
+            kind: MemoryRegionKind::Reserved,
+        }
+    }
+
+    pub fn end(&self) -> u64 {
+        self.base.saturating_add(self.length)
+    }
+}
+
+// GRUB's strings live in memory the kernel will reuse, so they are copied out
+#[derive(Clone, Copy)]
+struct BootString {
+    bytes: [u8; MAX_STRING_LEN],
+    len: usize,
+}
+
+impl BootString {
+    const fn empty() -> Self {
+        Self {
+            bytes: [0; MAX_STRING_LEN],
+            len: 0,
+        }
+    }
+
+    unsafe fn from_c_string(addr: u32) -> Self {
+        let mut string = Self::empty();
+        while string.len < MAX_STRING_LEN {
+            let Some(source) = boot_ptr::<u8>(addr + string.len as u32) else {
+                break;
+            };
+            let byte = ptr::read(source);
+            if byte == 0 {
+                break;
+            }
+            string.bytes[string.len] = byte;
+            string.len += 1;
+        }
+        string
+    }
+
+    fn as_str(&self) -> &str {
+        let bytes = &self.bytes[..self.len];
+        match core::str::from_utf8(bytes) {
+            Ok(string) => string,
+            Err(error) => core::str::from_utf8(&bytes[..error.valid_up_to()]).unwrap_or(""),
+        }
+    }
+}
+
+pub struct BootInfo {
+    flags: u32,
+    mem_lower: u32,
+    mem_upper: u32,
+    memory_map: [MemoryRegion; MAX_MEMORY_REGIONS],
+    memory_map_len: usize,
+    memory_map_truncated: bool,
+    boot_loader_name: Option<BootString>,
+    command_line: Option<BootString>,
+}
+
+impl BootInfo {
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    pub fn has_flag(&self, flag: u32) -> bool {
+        self.flags & flag != 0
+    }
+
+    // Both in KiB: conventional memory below 1 MiB, and memory from 1 MiB up to the first hole
+    pub fn mem_lower(&self) -> Option<u32> {
+        self.has_flag(FLAG_MEMORY).then_some(self.mem_lower)
+    }
+
+    pub fn mem_upper(&self) -> Option<u32> {
+        self.has_flag(FLAG_MEMORY).then_some(self.mem_upper)
+    }
+
+    pub fn memory_map(&self) -> &[MemoryRegion] {
+        &self.memory_map[..self.memory_map_len]
+    }
+
+    pub fn memory_map_truncated(&self) -> bool {
+        self.memory_map_truncated
+    }
+
+    pub fn available_memory(&self) -> u64 {
+        self.memory_map()
+            .iter()
+            .filter(|region| region.kind == MemoryRegionKind::Available)
+            .map(|region| region.length)
+            .sum()
+    }
+
+    pub fn boot_loader_name(&self) -> Option<&str> {
+        self.boot_loader_name.as_ref().map(BootString::as_str)
+    }
+
+    pub fn command_line(&self) -> Option<&str> {
+        self.command_line.as_ref().map(BootString::as_str)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum MultibootError {
+    InvalidMagic(u32),
+    NullInfoPointer,
+    InfoNotMapped(u32),
+}
+
+impl fmt::Display for MultibootError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MultibootError::InvalidMagic(magic) => write!(
+                f,
+                "invalid bootloader magic {:#010x}, expected {:#010x}",
+                magic, BOOTLOADER_MAGIC
+            ),
+            MultibootError::NullInfoPointer => write!(f, "no information structure was passed"),
+            MultibootError::InfoNotMapped(addr) => write!(
+                f,
+                "information structure at {:#010x} is above the first {} MiB mapped at boot",
+                addr,
+                BOOT_MAPPING_SIZE / (1024 * 1024)
+            ),
+        }
+    }
+}
+
+static mut BOOT_INFO: Option<BootInfo> = None;
+
+pub fn init_multiboot(magic: u32, info_addr: u32) -> Result<&'static BootInfo, MultibootError> {
+    if magic != BOOTLOADER_MAGIC {
+        return Err(MultibootError::InvalidMagic(magic));
+    }
+    if info_addr == 0 {
+        return Err(MultibootError::NullInfoPointer);
+    }
+    let Some(raw) = boot_ptr::<RawBootInfo>(info_addr) else {
+        return Err(MultibootError::InfoNotMapped(info_addr));
+    };
+
+    let info = unsafe { parse_boot_info(raw) };
+
+    printk!(
+        LogLevel::Info,
+        "Multiboot: loaded by {}, flags {:#x}\n",
+        info.boot_loader_name().unwrap_or("an unnamed bootloader"),
+        info.flags()
+    );
+    if let (Some(lower), Some(upper)) = (info.mem_lower(), info.mem_upper()) {
+        printk!(LogLevel::Info, "Multiboot: {} KiB lower, {} KiB upper memory\n", lower, upper);
+    }
+    printk!(
+        LogLevel::Info,
+        "Multiboot: {} memory map entries, {} KiB available\n",
+        info.memory_map().len(),
+        info.available_memory() / 1024
+    );
+    if info.memory_map_truncated() {
+        printk!(LogLevel::Warn, "Multiboot: memory map truncated to {} entries\n", MAX_MEMORY_REGIONS);
+    }
+
+    unsafe {
+        let boot_info = &raw mut BOOT_INFO;
+        *boot_info = Some(info);
+        Ok((*boot_info).as_ref().unwrap())
+    }
+}
+
+pub fn boot_info() -> Option<&'static BootInfo> {
+    unsafe {
+        let boot_info = &raw const BOOT_INFO;
+        (*boot_info).as_ref()
+    }
+}
+
+// GRUB hands out physical addresses, only the memory boot.asm mapped can be read this early
+fn boot_ptr<T>(addr: u32) -> Option<*const T> {
+    is_boot_mapped(addr, mem::size_of::<T>()).then(|| phys_to_virt(addr) as *const T)
+}
+
+unsafe fn parse_boot_info(raw: *const RawBootInfo) -> BootInfo {
+    let raw = ptr::read(raw);
+
+    let mut info = BootInfo {
+        flags: raw.flags,
+        mem_lower: 0,
+        mem_upper: 0,
+        memory_map: [MemoryRegion::empty(); MAX_MEMORY_REGIONS],
+        memory_map_len: 0,
+        memory_map_truncated: false,
+        boot_loader_name: None,
+        command_line: None,
+    };
+
+    if info.has_flag(FLAG_MEMORY) {
+        info.mem_lower = raw.mem_lower;
+        info.mem_upper = raw.mem_upper;
+    }
+
+    if info.has_flag(FLAG_MEMORY_MAP) {
+        // A corrupt map could carry the walk past the end of the address space, so every
+        // step is checked and the walk stops on overflow
+        let mut offset: u32 = 0;
+        while offset < raw.mmap_length {
+            // A whole entry must fit before the end of the map, a partial one is not read
+            let fits = offset
+                .checked_add(mem::size_of::<RawMemoryMapEntry>() as u32)
+                .is_some_and(|end| end <= raw.mmap_length);
+            if !fits {
+                info.memory_map_truncated = true;
+                break;
+            }
+            let Some(entry) = raw
+                .mmap_addr
+                .checked_add(offset)
+                .and_then(boot_ptr::<RawMemoryMapEntry>)
+            else {
+                info.memory_map_truncated = true;
+                break;
+            };
+            let entry = ptr::read_unaligned(entry);
+            if info.memory_map_len == MAX_MEMORY_REGIONS {
+                info.memory_map_truncated = true;
+                break;
+            }
+
+            info.memory_map[info.memory_map_len] = MemoryRegion {
+                base: entry.base_addr,
+                length: entry.length,
+                kind: MemoryRegionKind::from_raw(entry.kind),
+            };
+            info.memory_map_len += 1;
+
+            let Some(next) = entry.size.checked_add(4).and_then(|step| offset.checked_add(step)) else {
+                info.memory_map_truncated = true;
+                break;
+            };
+            offset = next;
+        }
+    }
+
+    if info.has_flag(FLAG_CMDLINE) && raw.cmdline != 0 {
+        info.command_line = Some(BootString::from_c_string(raw.cmdline));
+    }
+
+    if info.has_flag(FLAG_BOOT_LOADER_NAME) && raw.boot_loader_name != 0 {
+        info.boot_loader_name = Some(BootString::from_c_string(raw.boot_loader_name));
+    }
+
+    info
+}
@@ -0,0 +1,76 @@
+use core::fmt::Write;
+use crate::arch::x86::gdt::{analyse_gdt_entry, read_gdtr};
+use crate::arch::x86::stack::dump_stack;
+use crate::command::parse::parse_number;
+use crate::command::output::ShellOutput;
+use crate::command::shell::{CommandError, ShellCommand};
+
+pub struct Gdt;
+pub struct Stack;
+
+impl ShellCommand for Gdt {
+    fn name(&self) -> &'static str {
+        "gdt"
+    }
+
+    fn help(&self) -> &'static str {
+        "Show the decoded Global Descriptor Table"
+    }
+
+    fn run(&self, _args: &[&str], out: &mut ShellOutput) -> Result<(), CommandError> {
+        let gdtr = read_gdtr();
+        writeln!(
+            out,
+            "GDTR base={:#010x} limit={:#06x} ({} entries)",
+            gdtr.base, gdtr.limit, gdtr.entry_count()
+        )?;
+        writeln!(out, "Idx Sel    Base       Limit      DPL P Type        Flags")?;
+
+        for index in 0..gdtr.entry_count() {
+            if let Some(entry) = analyse_gdt_entry(&gdtr, index) {
+                writeln!(
+                    out,
+                    "{:>3} {:#06x} {:#010x} {:#010x}  {}  {} {:<11} {}{}{}{}",
+                    entry.index,
+                    entry.selector,
+                    entry.base,
+                    entry.limit,
+                    entry.dpl,
+                    if entry.present { 'P' } else { '-' },
+                    entry.kind,
+                    if entry.page_granular() { 'G' } else { '-' },
+                    if entry.protected_32() { 'D' } else { '-' },
+                    if entry.long_mode() { 'L' } else { '-' },
+                    if entry.available() { 'A' } else { '-' }
+                )?;
+            }
+        }
+        writeln!(out)?;
+        Ok(())
+    }
+}
+
+impl ShellCommand for Stack {
+    fn name(&self) -> &'static str {
+        "stack"
+    }
+
+    fn usage(&self) -> &'static str {
+        "[page]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Dump the kernel stack with its frames, a page at a time"
+    }
+
+    fn run(&self, args: &[&str], out: &mut ShellOutput) -> Result<(), CommandError> {
+        let page = match *args {
+            [] => 0,
+            [page] => parse_number(page).ok_or(CommandError::Usage)?,
+            _ => return Err(CommandError::Usage),
+        };
+        dump_stack(out, page)?;
+        writeln!(out)?;
+        Ok(())
+    }
+}
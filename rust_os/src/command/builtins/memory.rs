@@ -0,0 +1,371 @@
+use core::fmt::{self, Write};
+use core::ptr;
+use crate::arch::x86::paging::is_range_mapped;
+use crate::boot::multiboot::boot_info;
+use crate::command::parse::{parse_number, Width};
+use crate::command::output::ShellOutput;
+use crate::command::shell::{CommandError, ShellCommand};
+use crate::memory::frame::{frame_occupancy_map, frame_stats, FrameOccupancy, FRAME_SIZE};
+use crate::memory::heap::heap_stats;
+use crate::memory::slab::cache_stats;
+use crate::memory::vmalloc::vmalloc_stats;
+
+const FRAME_MAP_ROWS: usize = 16;
+const FRAME_MAP_COLUMNS: usize = 64;
+const HEXDUMP_BYTES_PER_LINE: u32 = 16;
+// Keeps the dump and the prompt after it on a single 25 row screen
+const MAX_HEXDUMP_LEN: u32 = 20 * HEXDUMP_BYTES_PER_LINE;
+
+pub struct Slabinfo;
+pub struct Meminfo;
+pub struct Mmap;
+pub struct Frames;
+pub struct Hexdump;
+pub struct Peek;
+pub struct Poke;
+
+impl ShellCommand for Slabinfo {
+    fn name(&self) -> &'static str {
+        "slabinfo"
+    }
+
+    fn help(&self) -> &'static str {
+        "Show the slab caches and their usage"
+    }
+
+    fn run(&self, _args: &[&str], out: &mut ShellOutput) -> Result<(), CommandError> {
+        let caches = cache_stats();
+        if caches.is_empty() {
+            writeln!(out, "No slab cache has been registered.")?;
+        } else {
+            writeln!(
+                out,
+                "Name           Size Stride Per slab Slabs  Active/Total    Allocs    Frees Fail"
+            )?;
+            for cache in &caches {
+                writeln!(
+                    out,
+                    "{:<14} {:>4} {:>6} {:>8} {:>5} {:>7}/{:<6} {:>8} {:>8} {:>4}",
+                    cache.name,
+                    cache.object_size,
+                    cache.stride,
+                    cache.objects_per_slab,
+                    cache.slabs,
+                    cache.active_objects,
+                    cache.total_objects,
+                    cache.allocations,
+                    cache.frees,
+                    cache.failed_allocations
+                )?;
+            }
+        }
+        writeln!(out)?;
+        Ok(())
+    }
+}
+
+impl ShellCommand for Meminfo {
+    fn name(&self) -> &'static str {
+        "meminfo"
+    }
+
+    fn help(&self) -> &'static str {
+        "Show physical, heap and vmalloc memory usage"
+    }
+
+    fn run(&self, _args: &[&str], out: &mut ShellOutput) -> Result<(), CommandError> {
+        let frames = frame_stats();
+        let heap = heap_stats();
+        let vmalloc = vmalloc_stats();
+        let frame_kib = FRAME_SIZE as usize / 1024;
+
+        if let Some(info) = boot_info() {
+            writeln!(out, "RAM reported by the bootloader: {} KiB", info.available_memory() / 1024)?;
+        }
+        writeln!(
+            out,
+            "Physical: {} KiB total, {} KiB used, {} KiB free",
+            frames.total_frames * frame_kib,
+            frames.used_frames * frame_kib,
+            frames.free_frames * frame_kib
+        )?;
+        writeln!(
+            out,
+            "  {} allocations, {} frees, {} failed, {} double frees",
+            frames.allocations, frames.frees, frames.failed_allocations, frames.double_frees
+        )?;
+        writeln!(
+            out,
+            "Heap: {} KiB mapped at {:#010x} (max {} KiB), {} KiB used, {} KiB free",
+            heap.mapped / 1024,
+            heap.start,
+            heap.limit / 1024,
+            heap.used / 1024,
+            heap.free / 1024
+        )?;
+        writeln!(
+            out,
+            "  {} allocations, {} frees, {} failed",
+            heap.allocations, heap.frees, heap.failed_allocations
+        )?;
+        writeln!(
+            out,
+            "vmalloc: {} areas, {} KiB mapped, break at {:#010x}",
+            vmalloc.areas,
+            vmalloc.mapped / 1024,
+            vmalloc.brk
+        )?;
+        writeln!(out)?;
+        Ok(())
+    }
+}
+
+impl ShellCommand for Mmap {
+    fn name(&self) -> &'static str {
+        "mmap"
+    }
+
+    fn help(&self) -> &'static str {
+        "Show the memory map passed by the bootloader"
+    }
+
+    fn run(&self, _args: &[&str], out: &mut ShellOutput) -> Result<(), CommandError> {
+        match boot_info() {
+            Some(info) if !info.memory_map().is_empty() => {
+                writeln!(out, "Memory map ({} entries):", info.memory_map().len())?;
+                writeln!(out, "Base               End                  Size KiB  Type")?;
+                for region in info.memory_map() {
+                    writeln!(
+                        out,
+                        "{:#018x} {:#018x} {:>10}  {}",
+                        region.base,
+                        region.end(),
+                        region.length / 1024,
+                        region.kind.name()
+                    )?;
+                }
+                if info.memory_map_truncated() {
+                    writeln!(out, "(truncated, more entries were passed)")?;
+                }
+            }
+            Some(_) => out.error(format_args!("The bootloader did not pass a memory map."))?,
+            None => out.error(format_args!("No multiboot information available."))?,
+        }
+        writeln!(out)?;
+        Ok(())
+    }
+}
+
+impl ShellCommand for Frames {
+    fn name(&self) -> &'static str {
+        "frames"
+    }
+
+    fn help(&self) -> &'static str {
+        "Show which physical frames are in use"
+    }
+
+    fn run(&self, _args: &[&str], out: &mut ShellOutput) -> Result<(), CommandError> {
+        let mut buckets = [FrameOccupancy::default(); FRAME_MAP_ROWS * FRAME_MAP_COLUMNS];
+        let per_bucket = frame_occupancy_map(&mut buckets);
+        let stats = frame_stats();
+
+        writeln!(
+            out,
+            "Frames: {} used, {} free of {} managed, {} KiB per cell",
+            stats.used_frames,
+            stats.free_frames,
+            stats.total_frames,
+            per_bucket * FRAME_SIZE as usize / 1024
+        )?;
+        writeln!(out, "'.' free  '#' used  '+' partly used  ' ' reserved")?;
+
+        for (row, cells) in buckets.chunks(FRAME_MAP_COLUMNS).enumerate() {
+            let start = (row * FRAME_MAP_COLUMNS * per_bucket) as u64 * FRAME_SIZE as u64;
+            write!(out, "{:#010x} ", start)?;
+            for cell in cells {
+                let symbol = match (cell.used, cell.free) {
+                    (0, 0) => ' ',
+                    (0, _) => '.',
+                    (_, 0) => '#',
+                    _ => '+',
+                };
+                out.write_char(symbol)?;
+            }
+            writeln!(out)?;
+        }
+        writeln!(out)?;
+        Ok(())
+    }
+}
+
+impl ShellCommand for Hexdump {
+    fn name(&self) -> &'static str {
+        "hexdump"
+    }
+
+    fn usage(&self) -> &'static str {
+        "<addr> <len>"
+    }
+
+    fn help(&self) -> &'static str {
+        "Dump memory as hex and ASCII"
+    }
+
+    fn run(&self, args: &[&str], out: &mut ShellOutput) -> Result<(), CommandError> {
+        let &[addr, len] = args else {
+            return Err(CommandError::Usage);
+        };
+        let (Some(addr), Some(len)) = (parse_number(addr), parse_number(len)) else {
+            return Err(CommandError::Usage);
+        };
+
+        let shown = len.min(MAX_HEXDUMP_LEN);
+        if !is_range_mapped(addr, shown, false) {
+            out.error(format_args!("hexdump: {:#010x}+{:#x} is not mapped", addr, shown))?;
+        } else {
+            write_hexdump(out, addr, shown)?;
+            if shown < len {
+                writeln!(out, "(limited to the first {} bytes)", MAX_HEXDUMP_LEN)?;
+            }
+        }
+        writeln!(out)?;
+        Ok(())
+    }
+}
+
+impl ShellCommand for Peek {
+    fn name(&self) -> &'static str {
+        "peek"
+    }
+
+    fn usage(&self) -> &'static str {
+        "<addr> [b|w|d]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Read a byte, word or dword"
+    }
+
+    fn run(&self, args: &[&str], out: &mut ShellOutput) -> Result<(), CommandError> {
+        let (addr, width) = match *args {
+            [addr] => (parse_number(addr), Some(Width::Dword)),
+            [addr, width] => (parse_number(addr), Width::parse(width)),
+            _ => return Err(CommandError::Usage),
+        };
+        let (Some(addr), Some(width)) = (addr, width) else {
+            return Err(CommandError::Usage);
+        };
+
+        match check_access(addr, width, false) {
+            Ok(()) => {
+                let value = unsafe { read_value(addr, width) };
+                let digits = width.bytes() as usize * 2;
+                writeln!(out, "{:#010x}: {:#0w$x} ({})", addr, value, value, w = digits + 2)?;
+            }
+            Err(reason) => out.error(format_args!("peek: {:#010x} {}", addr, reason))?,
+        }
+        Ok(())
+    }
+}
+
+impl ShellCommand for Poke {
+    fn name(&self) -> &'static str {
+        "poke"
+    }
+
+    fn usage(&self) -> &'static str {
+        "<addr> <value>"
+    }
+
+    fn help(&self) -> &'static str {
+        "Write a dword"
+    }
+
+    fn run(&self, args: &[&str], out: &mut ShellOutput) -> Result<(), CommandError> {
+        let &[addr, value] = args else {
+            return Err(CommandError::Usage);
+        };
+        let (Some(addr), Some(value)) = (parse_number(addr), parse_number(value)) else {
+            return Err(CommandError::Usage);
+        };
+        let width = Width::Dword;
+
+        match check_access(addr, width, true) {
+            Ok(()) => {
+                let (old, new) = unsafe {
+                    let old = read_value(addr, width);
+                    write_value(addr, width, value);
+                    (old, read_value(addr, width))
+                };
+                let w = width.bytes() as usize * 2 + 2;
+                writeln!(out, "{:#010x}: {:#0w$x} -> {:#0w$x}", addr, old, new, w = w)?;
+            }
+            Err(reason) => out.error(format_args!("poke: {:#010x} {}", addr, reason))?,
+        }
+        Ok(())
+    }
+}
+
+fn check_access(addr: u32, width: Width, write: bool) -> Result<(), &'static str> {
+    if !addr.is_multiple_of(width.bytes()) {
+        return Err("is not aligned to the access width");
+    }
+    if !is_range_mapped(addr, width.bytes(), write) {
+        return Err(if write { "is not mapped writable" } else { "is not mapped" });
+    }
+    Ok(())
+}
+
+unsafe fn read_value(addr: u32, width: Width) -> u32 {
+    let addr = addr as usize;
+    match width {
+        Width::Byte => ptr::read_volatile(addr as *const u8) as u32,
+        Width::Word => ptr::read_volatile(addr as *const u16) as u32,
+        Width::Dword => ptr::read_volatile(addr as *const u32),
+    }
+}
+
+unsafe fn write_value(addr: u32, width: Width, value: u32) {
+    let addr = addr as usize;
+    match width {
+        Width::Byte => ptr::write_volatile(addr as *mut u8, value as u8),
+        Width::Word => ptr::write_volatile(addr as *mut u16, value as u16),
+        Width::Dword => ptr::write_volatile(addr as *mut u32, value),
+    }
+}
+
+// Same layout as `hexdump -C`: address, two groups of eight bytes, then the printable ones
+fn write_hexdump(out: &mut dyn Write, addr: u32, len: u32) -> fmt::Result {
+    let mut offset = 0;
+    while offset < len {
+        let line = addr + offset;
+        let count = (len - offset).min(HEXDUMP_BYTES_PER_LINE) as usize;
+        let mut bytes = [0u8; HEXDUMP_BYTES_PER_LINE as usize];
+        for (index, byte) in bytes.iter_mut().take(count).enumerate() {
+            *byte = unsafe { ptr::read_volatile((line as usize + index) as *const u8) };
+        }
+
+        write!(out, "{:08x} ", line)?;
+        for (index, byte) in bytes.iter().enumerate() {
+            if index == 8 {
+                write!(out, " ")?;
+            }
+            if index < count {
+                write!(out, " {:02x}", byte)?;
+            } else {
+                write!(out, "   ")?;
+            }
+        }
+
+        write!(out, "  |")?;
+        for &byte in &bytes[..count] {
+            let shown = if (0x20..0x7f).contains(&byte) { byte as char } else { '.' };
+            write!(out, "{}", shown)?;
+        }
+        writeln!(out, "|")?;
+
+        offset += count as u32;
+    }
+    Ok(())
+}
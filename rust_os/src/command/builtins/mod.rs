@@ -0,0 +1,23 @@
+pub mod debug;
+pub mod memory;
+pub mod system;
+
+use super::shell::ShellCommand;
+
+// help lists the commands in this order
+pub static COMMANDS: &[&dyn ShellCommand] = &[
+    &system::Help,
+    &system::Clear,
+    &system::Reboot,
+    &system::Halt,
+    &system::History,
+    &debug::Gdt,
+    &debug::Stack,
+    &memory::Slabinfo,
+    &memory::Meminfo,
+    &memory::Mmap,
+    &memory::Frames,
+    &memory::Hexdump,
+    &memory::Peek,
+    &memory::Poke,
+];
\ No newline at end of file
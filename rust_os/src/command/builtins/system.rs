@@ -0,0 +1,177 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use crate::arch::x86::port::{inb, outb};
+use crate::command::history::history;
+use crate::command::output::{flush_shell_screen, ShellOutput, SHELL_SCREEN_ID};
+use crate::command::shell::{commands, CommandError, ShellCommand};
+use crate::screen::global::screen_manager;
+
+pub struct Help;
+pub struct Clear;
+pub struct Reboot;
+pub struct Halt;
+pub struct History;
+
+impl ShellCommand for Help {
+    fn name(&self) -> &'static str {
+        "help"
+    }
+
+    fn help(&self) -> &'static str {
+        "Show this help message"
+    }
+
+    fn run(&self, _args: &[&str], out: &mut ShellOutput) -> Result<(), CommandError> {
+        writeln!(out, "Available commands:")?;
+        for command in commands() {
+            let synopsis = if command.usage().is_empty() {
+                String::from(command.name())
+            } else {
+                format!("{} {}", command.name(), command.usage())
+            };
+            writeln!(out, "  {:<27} - {}", synopsis, command.help())?;
+        }
+        writeln!(out, "  Numbers are decimal, or hexadecimal with a 0x prefix")?;
+        writeln!(out, "  Quote arguments with '...' or \"...\", or escape a character with \\")?;
+        writeln!(out)?;
+        Ok(())
+    }
+}
+
+impl ShellCommand for Clear {
+    fn name(&self) -> &'static str {
+        "clear"
+    }
+
+    fn help(&self) -> &'static str {
+        "Clear the screen"
+    }
+
+    fn run(&self, _args: &[&str], _out: &mut ShellOutput) -> Result<(), CommandError> {
+        let mut manager = screen_manager().lock();
+        if manager.clear_screen(SHELL_SCREEN_ID) {
+            if let Some(screen) = manager.get_screen_mut(SHELL_SCREEN_ID) {
+                screen.set_cursor_position(0, 0);
+            }
+            if manager.get_active_screen_id() == SHELL_SCREEN_ID {
+                manager.flush_to_physical();
+                manager.update_cursor();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ShellCommand for Reboot {
+    fn name(&self) -> &'static str {
+        "reboot"
+    }
+
+    fn help(&self) -> &'static str {
+        "Restart the system"
+    }
+
+    fn run(&self, _args: &[&str], out: &mut ShellOutput) -> Result<(), CommandError> {
+        writeln!(out, "Rebooting system...")?;
+        flush_shell_screen();
+
+        unsafe {
+            while (inb(0x64) & 0x02) != 0 {}
+            outb(0x64, 0xFE);
+        }
+
+        // The keyboard controller did not reset us, a triple fault will
+        unsafe {
+            #[repr(C, packed)]
+            struct InvalidIDT {
+                limit: u16,
+                base: u64,
+            }
+
+            let invalid_idt = InvalidIDT {
+                limit: 0,
+                base: 0,
+            };
+
+            core::arch::asm!(
+                "lidt [{}]",
+                in(reg) &invalid_idt,
+                options(nostack, preserves_flags)
+            );
+
+            core::arch::asm!("int 3", options(nostack, preserves_flags));
+        }
+        Ok(())
+    }
+}
+
+impl ShellCommand for Halt {
+    fn name(&self) -> &'static str {
+        "halt"
+    }
+
+    fn help(&self) -> &'static str {
+        "Halt the system (safe to power off)"
+    }
+
+    fn run(&self, _args: &[&str], out: &mut ShellOutput) -> Result<(), CommandError> {
+        writeln!(out, "System halted. Safe to power off.")?;
+        flush_shell_screen();
+
+        unsafe {
+            core::arch::asm!(
+                "cli",
+                "hlt",
+                options(nostack, preserves_flags)
+            );
+        }
+
+        loop {
+            unsafe {
+                core::arch::asm!(
+                    "hlt",
+                    options(nostack, preserves_flags)
+                );
+            }
+        }
+    }
+}
+
+
+impl ShellCommand for History {
+    fn name(&self) -> &'static str {
+        "history"
+    }
+
+    fn usage(&self) -> &'static str {
+        "[-c]"
+    }
+
+    fn help(&self) -> &'static str {
+        "List previous command lines, -c forgets them"
+    }
+
+    fn run(&self, args: &[&str], out: &mut ShellOutput) -> Result<(), CommandError> {
+        match args {
+            [] => {}
+            ["-c"] => {
+                history().lock().clear();
+                return Ok(());
+            }
+            _ => return Err(CommandError::Usage),
+        }
+
+        // Copied out so the history lock is not held while the screen is written
+        let lines: Vec<(usize, String)> = history()
+            .lock()
+            .iter()
+            .map(|(number, line)| (number, String::from_utf8_lossy(line).into_owned()))
+            .collect();
+        for (number, line) in &lines {
+            writeln!(out, "{:>5}  {}", number, line)?;
+        }
+        Ok(())
+    }
+}
\ No newline at end of file
@@ -1,21 +1,28 @@
-use crate::screen::global::screen_manager;
-use crate::screen::screen::{Writer, BUFFER_WIDTH};
-use crate::arch::x86::port::outb;
-
-#[derive(Debug, Clone, Copy)]
-pub enum Command {
-    Reboot,
-    Halt,
-    Clear,
-    Help,
-    Unknown,
-}
+use core::fmt::Write;
+use crate::screen::manager::ScreenManager;
+use crate::screen::screen::{Writer, BUFFER_HEIGHT, BUFFER_WIDTH};
+use super::history::history;
+use super::output::{ShellOutput, SHELL_SCREEN_ID};
+use super::shell::{commands, run_command_line};
+
+pub const PROMPT: &str = "> ";
 
 pub struct CommandHandler {
     buffer: [u8; 256],
     buffer_len: usize,
-    prompt_start_col: usize, 
+    // Index into the buffer, the screen cursor is derived from it on every redraw
+    cursor: usize,
+    // Where the input starts; the line wraps onto the following rows from there
+    prompt_start_col: usize,
     prompt_start_row: usize,
+    // How far back Up has gone, None while the line being typed is shown
+    history_age: Option<usize>,
+    // The line being typed when Up was first pressed, brought back by Down
+    draft: [u8; 256],
+    draft_len: usize,
+    // Text removed by the last Ctrl+K/U/W, put back by Ctrl+Y
+    kill: [u8; 256],
+    kill_len: usize,
 }
 
 impl CommandHandler {
@@ -23,8 +30,14 @@ impl CommandHandler {
         Self {
             buffer: [0; 256],
             buffer_len: 0,
+            cursor: 0,
             prompt_start_col: 0,
             prompt_start_row: 0,
+            history_age: None,
+            draft: [0; 256],
+            draft_len: 0,
+            kill: [0; 256],
+            kill_len: 0,
         }
     }
 
@@ -33,347 +46,340 @@ impl CommandHandler {
         self.prompt_start_col = col;
     }
 
-    pub fn add_char(&mut self, ch: u8, manager: &mut crate::screen::manager::ScreenManager) {
-        if self.buffer_len < self.buffer.len() - 1 && ch != b'\n' {
-            if let Some(screen) = manager.get_screen_mut(2) {
-                let cursor_pos = screen.column_position.saturating_sub(self.prompt_start_col);
-                
-                if cursor_pos < self.buffer_len {
-                    for i in (cursor_pos..self.buffer_len).rev() {
-                        self.buffer[i + 1] = self.buffer[i];
-                    }
-                }
-                
-                self.buffer[cursor_pos] = ch;
-                self.buffer_len += 1;
-                
-                if cursor_pos == self.buffer_len - 1 {
-                    let mut writer = Writer::new(screen);
-                    writer.write_byte(ch);
-                } else {
-                    let start_col = self.prompt_start_col + cursor_pos;
-                    let current_row = screen.row_position;
-                    
-                    for i in cursor_pos..self.buffer_len {
-                        let col = start_col + (i - cursor_pos);
-                        if col < BUFFER_WIDTH {
-                            screen.write_byte_at(current_row, col, self.buffer[i]);
-                        }
-                    }
-                    
-                    screen.column_position = self.prompt_start_col + cursor_pos + 1;
-                }
-                
-                if manager.get_active_screen_id() == 2 {
-                    manager.flush_to_physical();
-                    manager.update_cursor();
+    // Draws the input after the prompt, wrapped over as many rows as it takes, and puts
+    // the screen cursor on `self.cursor`. The first `old_len` cells are blanked too, so a
+    // line that got shorter leaves nothing behind.
+    fn render(&mut self, old_len: usize, manager: &mut ScreenManager) {
+        if let Some(screen) = manager.get_screen_mut(SHELL_SCREEN_ID) {
+            // A cursor after the last byte can need a row of its own. Scrolling it into
+            // view moves the prompt up along with the text.
+            let end = self.prompt_start_row * BUFFER_WIDTH + self.prompt_start_col + self.buffer_len;
+            let last_row = end / BUFFER_WIDTH;
+            if last_row >= BUFFER_HEIGHT {
+                let lines = last_row + 1 - BUFFER_HEIGHT;
+                for _ in 0..lines {
+                    screen.scroll_up();
                 }
+                self.prompt_start_row = self.prompt_start_row.saturating_sub(lines);
             }
-        }
-    }
 
-    pub fn delete_char(&mut self, manager: &mut crate::screen::manager::ScreenManager) {
-        if let Some(screen) = manager.get_screen_mut(2) {
-            let cursor_pos = screen.column_position.saturating_sub(self.prompt_start_col);
-            
-            if cursor_pos < self.buffer_len {
-                for i in cursor_pos..self.buffer_len - 1 {
-                    self.buffer[i] = self.buffer[i + 1];
-                }
-                
-                self.buffer_len -= 1;
-                self.buffer[self.buffer_len] = 0;
-                
-                let row_pos = screen.row_position;
-                let col_pos = screen.column_position;
-                
-                screen.write_byte_at(row_pos, col_pos, b' ');
-                
-                for i in 0..self.buffer_len - cursor_pos {
-                    let col = self.prompt_start_col + cursor_pos + i;
-                    if col < BUFFER_WIDTH {
-                        screen.write_byte_at(row_pos, col, self.buffer[cursor_pos + i]);
-                    }
-                }
-                
-                let trailing_col = self.prompt_start_col + self.buffer_len;
-                if trailing_col < BUFFER_WIDTH {
-                    screen.write_byte_at(row_pos, trailing_col, b' ');
-                }
+            let start = self.prompt_start_row * BUFFER_WIDTH + self.prompt_start_col;
+            for i in 0..self.buffer_len.max(old_len) {
+                let byte = if i < self.buffer_len { self.buffer[i] } else { b' ' };
+                let cell = start + i;
+                screen.write_byte_at(cell / BUFFER_WIDTH, cell % BUFFER_WIDTH, byte);
             }
-            
-            if manager.get_active_screen_id() == 2 {
+
+            let cell = start + self.cursor;
+            screen.set_cursor_position(cell / BUFFER_WIDTH, cell % BUFFER_WIDTH);
+
+            if manager.get_active_screen_id() == SHELL_SCREEN_ID {
                 manager.flush_to_physical();
                 manager.update_cursor();
             }
         }
     }
 
-    pub fn backspace(&mut self, manager: &mut crate::screen::manager::ScreenManager) {
-        if let Some(screen) = manager.get_screen_mut(2) {
-            let cursor_pos = screen.column_position.saturating_sub(self.prompt_start_col);
-            
-            if cursor_pos > 0 {
-                screen.column_position -= 1;
-                
-                for i in cursor_pos - 1..self.buffer_len - 1 {
-                    self.buffer[i] = self.buffer[i + 1];
-                }
-                
-                self.buffer_len -= 1;
-                self.buffer[self.buffer_len] = 0;
-                
-                let row_pos = screen.row_position;
-                let col_pos = screen.column_position;
-                
-                screen.write_byte_at(row_pos, col_pos, b' ');
-                
-                for i in 0..self.buffer_len - (cursor_pos - 1) {
-                    let col = self.prompt_start_col + (cursor_pos - 1) + i;
-                    if col < BUFFER_WIDTH {
-                        screen.write_byte_at(row_pos, col, self.buffer[(cursor_pos - 1) + i]);
-                    }
-                }
-                
-                let trailing_col = self.prompt_start_col + self.buffer_len;
-                if trailing_col < BUFFER_WIDTH {
-                    screen.write_byte_at(row_pos, trailing_col, b' ');
-                }
-            }
-            
-            if manager.get_active_screen_id() == 2 {
-                manager.flush_to_physical();
-                manager.update_cursor();
-            }
-        }
+    fn move_cursor_to(&mut self, cursor: usize, manager: &mut ScreenManager) {
+        self.cursor = cursor.min(self.buffer_len);
+        self.render(self.buffer_len, manager);
     }
 
-    pub fn move_cursor_left(&mut self, manager: &mut crate::screen::manager::ScreenManager) {
-        if let Some(screen) = manager.get_screen_mut(2) {
-            let cursor_pos = screen.column_position.saturating_sub(self.prompt_start_col);
-            if cursor_pos > 0 {
-                screen.column_position -= 1;
-            }
-            
-            if manager.get_active_screen_id() == 2 {
-                manager.update_cursor();
-            }
+    pub fn add_char(&mut self, ch: u8, manager: &mut ScreenManager) {
+        // Only printable ASCII goes in the line, anything else would show up as a VGA glyph
+        if self.buffer_len < self.buffer.len() - 1 && (ch == b' ' || ch.is_ascii_graphic()) {
+            self.buffer.copy_within(self.cursor..self.buffer_len, self.cursor + 1);
+            self.buffer[self.cursor] = ch;
+            self.buffer_len += 1;
+            self.cursor += 1;
+            self.render(self.buffer_len, manager);
         }
     }
 
-    pub fn move_cursor_right(&mut self, manager: &mut crate::screen::manager::ScreenManager) {
-        if let Some(screen) = manager.get_screen_mut(2) {
-            let cursor_pos = screen.column_position.saturating_sub(self.prompt_start_col);
-            if cursor_pos < self.buffer_len {
-                screen.column_position += 1;
-            }
-            
-            if manager.get_active_screen_id() == 2 {
-                manager.update_cursor();
-            }
+    pub fn delete_char(&mut self, manager: &mut ScreenManager) {
+        if self.cursor < self.buffer_len {
+            self.remove_range(self.cursor, self.cursor + 1, manager);
         }
     }
 
-    pub fn move_cursor_home(&mut self, manager: &mut crate::screen::manager::ScreenManager) {
-        if let Some(screen) = manager.get_screen_mut(2) {
-            screen.column_position = self.prompt_start_col;
-            
-            if manager.get_active_screen_id() == 2 {
-                manager.update_cursor();
-            }
+    pub fn backspace(&mut self, manager: &mut ScreenManager) {
+        if self.cursor > 0 {
+            self.remove_range(self.cursor - 1, self.cursor, manager);
         }
     }
 
-    pub fn move_cursor_end(&mut self, manager: &mut crate::screen::manager::ScreenManager) {
-        if let Some(screen) = manager.get_screen_mut(2) {
-            screen.column_position = self.prompt_start_col.saturating_add(self.buffer_len);
-            
-            if manager.get_active_screen_id() == 2 {
-                manager.update_cursor();
-            }
+    pub fn move_cursor_left(&mut self, manager: &mut ScreenManager) {
+        if self.cursor > 0 {
+            self.move_cursor_to(self.cursor - 1, manager);
         }
     }
 
-    pub fn execute_command(&mut self) -> bool {
-        if self.buffer_len == 0 {
-            return false;
-        }
+    pub fn move_cursor_right(&mut self, manager: &mut ScreenManager) {
+        self.move_cursor_to(self.cursor + 1, manager);
+    }
 
-        let command_str = core::str::from_utf8(&self.buffer[..self.buffer_len])
-            .unwrap_or("");
+    pub fn move_cursor_home(&mut self, manager: &mut ScreenManager) {
+        self.move_cursor_to(0, manager);
+    }
 
-        let command = self.parse_command(command_str);
-        self.handle_command(command);
-        
-        self.clear_buffer();
-        true
+    pub fn move_cursor_end(&mut self, manager: &mut ScreenManager) {
+        self.move_cursor_to(self.buffer_len, manager);
     }
 
-    fn parse_command(&self, input: &str) -> Command {
-        match input.trim() {
-            "reboot" => Command::Reboot,
-            "clear" => Command::Clear,
-            "help" => Command::Help,
-            "halt" => Command::Halt,
-            _ => Command::Unknown,
+    // Puts the cursor after the whole input, however many rows it spans, then starts a new row
+    pub fn finish_line(&mut self, manager: &mut ScreenManager) {
+        self.move_cursor_end(manager);
+        if let Some(screen) = manager.get_screen_mut(SHELL_SCREEN_ID) {
+            let mut writer = Writer::new(screen);
+            writer.write_byte(b'\n');
         }
-    }
 
-    fn handle_command(&self, command: Command) {
-        match command {
-            Command::Reboot => {
-                self.execute_reboot();
-            }
-            Command::Clear => {
-                self.execute_clear();
-            }
-            Command::Help => {
-                self.execute_help();
-            }
-            Command::Halt => {
-                self.execute_halt();
-            }
-            Command::Unknown => {
-                self.execute_unknown();
-            }
+        if manager.get_active_screen_id() == SHELL_SCREEN_ID {
+            manager.flush_to_physical();
+            manager.update_cursor();
         }
     }
 
-    fn execute_clear(&self) {
-        let mut manager = screen_manager().lock();
-        if manager.clear_screen(2) {
-            if let Some(screen) = manager.get_screen_mut(2) {
-                screen.set_cursor_position(0, 0);
+    // Completes the command name in front of the cursor. Several candidates are first
+    // narrowed to their common prefix, then listed under the prompt.
+    pub fn complete(&mut self, manager: &mut ScreenManager) {
+        let before_cursor = &self.buffer[..self.cursor];
+        let word_start = before_cursor
+            .iter()
+            .position(|byte| !byte.is_ascii_whitespace())
+            .unwrap_or(self.cursor);
+        let prefix = &before_cursor[word_start..];
+        if prefix.iter().any(u8::is_ascii_whitespace) {
+            return;
+        }
+
+        let mut candidates = commands()
+            .iter()
+            .map(|command| command.name())
+            .filter(|name| name.as_bytes().starts_with(prefix));
+        let Some(first) = candidates.next() else {
+            return;
+        };
+        let common = candidates.clone().fold(first.len(), |len, name| {
+            first
+                .bytes()
+                .zip(name.bytes())
+                .take(len)
+                .take_while(|(a, b)| a == b)
+                .count()
+        });
+        let several = candidates.next().is_some();
+        let prefix_len = prefix.len();
+        let at_end = self.cursor == self.buffer_len;
+
+        if common > prefix_len {
+            for &byte in &first.as_bytes()[prefix_len..common] {
+                self.add_char(byte, manager);
             }
-            if manager.get_active_screen_id() == 2 {
-                manager.flush_to_physical();
-                manager.update_cursor();
+            if !several && at_end {
+                self.add_char(b' ', manager);
             }
+        } else if several {
+            self.list_candidates(prefix_len, manager);
         }
     }
 
-    fn execute_help(&self) {
-        let mut manager = screen_manager().lock();
-        if let Some(screen) = manager.get_screen_mut(2) {
+    fn list_candidates(&mut self, prefix_len: usize, manager: &mut ScreenManager) {
+        let cursor = self.cursor;
+        self.finish_line(manager);
+        self.cursor = cursor;
+
+        let prefix = &self.buffer[cursor - prefix_len..cursor];
+        if let Some(screen) = manager.get_screen_mut(SHELL_SCREEN_ID) {
             let mut writer = Writer::new(screen);
-            for byte in b"Available commands:\n" {
-                writer.write_byte(*byte);
-            }
-            for byte in b"  help   - Show this help message\n" {
-                writer.write_byte(*byte);
-            }
-            for byte in b"  clear  - Clear the screen\n" {
-                writer.write_byte(*byte);
-            }
-            for byte in b"  reboot - Restart the system\n" {
-                writer.write_byte(*byte);
+            let mut column = 0;
+            for name in commands().iter().map(|command| command.name()) {
+                if !name.as_bytes().starts_with(prefix) {
+                    continue;
+                }
+                if column != 0 && column + name.len() >= BUFFER_WIDTH {
+                    writer.write_byte(b'\n');
+                    column = 0;
+                }
+                let _ = write!(writer, "{}  ", name);
+                column += name.len() + 2;
             }
-            for byte in b"  halt   - Halt the system (safe to power off)\n" {
-                writer.write_byte(*byte);
-            }
-            writer.write_byte(b'\n');
-            
-            if manager.get_active_screen_id() == 2 {
-                manager.flush_to_physical();
-                manager.update_cursor();
+            if column != 0 {
+                writer.write_byte(b'\n');
             }
         }
+
+        // The prompt and the line come back below the list, the cursor where it was
+        self.new_prompt(manager);
     }
 
-    fn execute_unknown(&self) {
-        let mut manager = screen_manager().lock();
-        if let Some(screen) = manager.get_screen_mut(2) {
+    // Writes the prompt where the screen cursor is and draws the line after it
+    fn new_prompt(&mut self, manager: &mut ScreenManager) {
+        if let Some(screen) = manager.get_screen_mut(SHELL_SCREEN_ID) {
             let mut writer = Writer::new(screen);
-            for byte in b"Unknown command. Type 'help' for available commands.\n" {
-                writer.write_byte(*byte);
-            }
-            
-            if manager.get_active_screen_id() == 2 {
-                manager.flush_to_physical();
-                manager.update_cursor();
-            }
+            let _ = writer.write_str(PROMPT);
+            self.set_prompt_position(screen.row_position, screen.column_position);
         }
+        self.render(self.buffer_len, manager);
     }
 
-    fn execute_reboot(&self) {
-        {
-            let mut manager = screen_manager().lock();
-            if let Some(screen) = manager.get_screen_mut(2) {
-                let mut writer = Writer::new(screen);
-                for byte in b"Rebooting system...\n" {
-                    writer.write_byte(*byte);
+    pub fn history_previous(&mut self, manager: &mut ScreenManager) {
+        let age = self.history_age.map_or(0, |age| age + 1);
+        let mut line = [0u8; 256];
+        let len = {
+            let history = history().lock();
+            match history.get(age) {
+                Some(entry) => {
+                    line[..entry.len()].copy_from_slice(entry);
+                    entry.len()
                 }
-                
-                if manager.get_active_screen_id() == 2 {
-                    manager.flush_to_physical();
-                    manager.update_cursor();
+                None => return,
+            }
+        };
+
+        if self.history_age.is_none() {
+            self.draft = self.buffer;
+            self.draft_len = self.buffer_len;
+        }
+        self.history_age = Some(age);
+        self.replace_line(&line[..len], manager);
+    }
+
+    pub fn history_next(&mut self, manager: &mut ScreenManager) {
+        match self.history_age {
+            None => {}
+            Some(0) => {
+                self.history_age = None;
+                let draft = self.draft;
+                self.replace_line(&draft[..self.draft_len], manager);
+            }
+            Some(age) => {
+                let mut line = [0u8; 256];
+                let len = {
+                    let history = history().lock();
+                    history.get(age - 1).map(|entry| {
+                        line[..entry.len()].copy_from_slice(entry);
+                        entry.len()
+                    })
+                };
+                if let Some(len) = len {
+                    self.history_age = Some(age - 1);
+                    self.replace_line(&line[..len], manager);
                 }
             }
         }
+    }
+
+    // Swaps the input for `line` on screen and in the buffer, leaving the cursor at its end
+    fn replace_line(&mut self, line: &[u8], manager: &mut ScreenManager) {
+        let len = line.len().min(self.buffer.len() - 1);
+        let old_len = self.buffer_len;
+        self.buffer = [0; 256];
+        self.buffer[..len].copy_from_slice(&line[..len]);
+        self.buffer_len = len;
+        self.cursor = len;
+        self.render(old_len, manager);
+    }
 
-        unsafe {
-            while (crate::arch::x86::port::inb(0x64) & 0x02) != 0 {}
-            outb(0x64, 0xFE);
+    // Emacs style bindings, `key` is the lowercase letter pressed with Ctrl
+    pub fn handle_ctrl(&mut self, key: char, manager: &mut ScreenManager) {
+        match key {
+            'a' => self.move_cursor_home(manager),
+            'e' => self.move_cursor_end(manager),
+            'k' => self.kill_range(self.cursor, self.buffer_len, manager),
+            'u' => self.kill_range(0, self.cursor, manager),
+            'w' => self.kill_word(manager),
+            'y' => self.yank(manager),
+            'l' => self.redraw_screen(manager),
+            'c' => self.abandon_line(manager),
+            _ => {}
         }
+    }
 
-        unsafe {
-            #[repr(C, packed)]
-            struct InvalidIDT {
-                limit: u16,
-                base: u64,
-            }
-            
-            let invalid_idt = InvalidIDT {
-                limit: 0,
-                base: 0,
-            };
-            
-            core::arch::asm!(
-                "lidt [{}]",
-                in(reg) &invalid_idt,
-                options(nostack, preserves_flags)
-            );
-            
-            core::arch::asm!("int 3", options(nostack, preserves_flags));
+    // Takes buffer[start..end] out of the line and closes the gap, cursor at `start`
+    fn remove_range(&mut self, start: usize, end: usize, manager: &mut ScreenManager) {
+        let old_len = self.buffer_len;
+        self.buffer.copy_within(end..old_len, start);
+        self.buffer_len -= end - start;
+        self.buffer[self.buffer_len..old_len].fill(0);
+        self.cursor = start;
+        self.render(old_len, manager);
+    }
+
+    // Same, but the text is kept for Ctrl+Y
+    fn kill_range(&mut self, start: usize, end: usize, manager: &mut ScreenManager) {
+        if start >= end {
+            return;
         }
+        self.kill[..end - start].copy_from_slice(&self.buffer[start..end]);
+        self.kill_len = end - start;
+        self.remove_range(start, end, manager);
     }
 
-    fn execute_halt(&self) {
-        {
-            let mut manager = screen_manager().lock();
-            if let Some(screen) = manager.get_screen_mut(2) {
-                let mut writer = Writer::new(screen);
-                for byte in b"System halted. Safe to power off.\n" {
-                    writer.write_byte(*byte);
-                }
-                
-                if manager.get_active_screen_id() == 2 {
-                    manager.flush_to_physical();
-                    manager.update_cursor();
-                }
-            }
+    // Like a terminal's Ctrl+W: the whitespace before the cursor, then the word before that
+    fn kill_word(&mut self, manager: &mut ScreenManager) {
+        let before = &self.buffer[..self.cursor];
+        let word_end = before
+            .iter()
+            .rposition(|byte| !byte.is_ascii_whitespace())
+            .map_or(0, |index| index + 1);
+        let word_start = before[..word_end]
+            .iter()
+            .rposition(u8::is_ascii_whitespace)
+            .map_or(0, |index| index + 1);
+        self.kill_range(word_start, self.cursor, manager);
+    }
+
+    fn yank(&mut self, manager: &mut ScreenManager) {
+        let kill = self.kill;
+        for &byte in &kill[..self.kill_len] {
+            self.add_char(byte, manager);
         }
+    }
 
-        unsafe {
-            core::arch::asm!(
-                "cli",
-                "hlt",
-                options(nostack, preserves_flags)
-            );
+    // Clears the screen and draws the prompt and the line again at the top
+    fn redraw_screen(&mut self, manager: &mut ScreenManager) {
+        manager.clear_screen(SHELL_SCREEN_ID);
+        if let Some(screen) = manager.get_screen_mut(SHELL_SCREEN_ID) {
+            screen.set_cursor_position(0, 0);
         }
+        self.new_prompt(manager);
+    }
 
-        loop {
-            unsafe {
-                core::arch::asm!(
-                    "hlt",
-                    options(nostack, preserves_flags)
-                );
-            }
+    // Ctrl+C: leaves the line on screen marked with ^C and starts a fresh prompt
+    fn abandon_line(&mut self, manager: &mut ScreenManager) {
+        self.move_cursor_end(manager);
+        if let Some(screen) = manager.get_screen_mut(SHELL_SCREEN_ID) {
+            let mut writer = Writer::new(screen);
+            let _ = writer.write_str("^C\n");
+        }
+        self.clear_buffer();
+        self.history_age = None;
+        self.new_prompt(manager);
+    }
+
+    pub fn execute_command(&mut self) -> bool {
+        self.history_age = None;
+        if self.buffer_len == 0 {
+            return false;
         }
+
+        history().lock().push(&self.buffer[..self.buffer_len]);
+
+        let command_str = core::str::from_utf8(&self.buffer[..self.buffer_len])
+            .unwrap_or("");
+
+        let mut out = ShellOutput::new();
+        run_command_line(command_str, &mut out);
+        out.flush();
+
+        self.clear_buffer();
+        true
     }
 
     fn clear_buffer(&mut self) {
         self.buffer_len = 0;
+        self.cursor = 0;
         for i in 0..self.buffer.len() {
             self.buffer[i] = 0;
         }
@@ -0,0 +1,103 @@
+use crate::kspin_lock::KSpinLock;
+
+pub const HISTORY_CAPACITY: usize = 32;
+// Same as the command handler's line buffer, nothing longer can be typed
+pub const HISTORY_LINE_LEN: usize = 256;
+
+#[derive(Clone, Copy)]
+struct HistoryEntry {
+    line: [u8; HISTORY_LINE_LEN],
+    len: usize,
+}
+
+impl HistoryEntry {
+    const EMPTY: Self = Self {
+        line: [0; HISTORY_LINE_LEN],
+        len: 0,
+    };
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.line[..self.len]
+    }
+}
+
+// Ring of the last HISTORY_CAPACITY command lines, the oldest one is overwritten first
+pub struct History {
+    entries: [HistoryEntry; HISTORY_CAPACITY],
+    // Slot the next line goes into
+    next: usize,
+    len: usize,
+    // Lines recorded since boot, numbers the entries like a shell does
+    recorded: usize,
+}
+
+static HISTORY: KSpinLock<History> = KSpinLock::new(History::new());
+
+impl History {
+    pub const fn new() -> Self {
+        Self {
+            entries: [HistoryEntry::EMPTY; HISTORY_CAPACITY],
+            next: 0,
+            len: 0,
+            recorded: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Blank lines and repeats of the newest entry are not recorded
+    pub fn push(&mut self, line: &[u8]) {
+        if line.iter().all(u8::is_ascii_whitespace) || self.get(0) == Some(line) {
+            return;
+        }
+
+        let len = line.len().min(HISTORY_LINE_LEN);
+        let entry = &mut self.entries[self.next];
+        entry.line[..len].copy_from_slice(&line[..len]);
+        entry.len = len;
+
+        self.next = (self.next + 1) % HISTORY_CAPACITY;
+        self.len = (self.len + 1).min(HISTORY_CAPACITY);
+        self.recorded += 1;
+    }
+
+    // `age` 0 is the most recent line
+    pub fn get(&self, age: usize) -> Option<&[u8]> {
+        if age >= self.len {
+            return None;
+        }
+        let slot = (self.next + HISTORY_CAPACITY - 1 - age) % HISTORY_CAPACITY;
+        Some(self.entries[slot].as_bytes())
+    }
+
+    // Oldest first, with the number each line was recorded under
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &[u8])> {
+        let first = self.recorded - self.len + 1;
+        (0..self.len)
+            .rev()
+            .filter_map(move |age| self.get(age))
+            .enumerate()
+            .map(move |(index, line)| (first + index, line))
+    }
+
+    pub fn clear(&mut self) {
+        self.next = 0;
+        self.len = 0;
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn history() -> &'static KSpinLock<History> {
+    &HISTORY
+}
@@ -2,6 +2,7 @@ use core::fmt::Write;
 use crate::screen::global::screen_manager;
 use crate::screen::screen::Writer;
 use crate::command::CommandHandler;
+use crate::command::command_handler::PROMPT;
 use crate::kspin_lock::kspin_lock::KSpinLock;
 use crate::printk;
 
@@ -15,7 +16,7 @@ pub fn init_command_handler() {
         write!(writer, "#                             Welcome to the User Terminal                     #\n").unwrap();
         write!(writer, "\n").unwrap();
         write!(writer, "Type 'help' for available commands.\n").unwrap();
-        write!(writer, "> ").unwrap();
+        write!(writer, "{}", PROMPT).unwrap();
         
         let prompt_row = screen.row_position;
         let prompt_col = screen.column_position;
@@ -1,5 +1,11 @@
+pub mod builtins;
 pub mod command_handler;
+pub mod history;
 pub mod init;
+pub mod output;
+pub mod parse;
+pub mod shell;
 
 pub use command_handler::CommandHandler;
-pub use init::{init_command_handler, command_handler};
\ No newline at end of file
+pub use init::{init_command_handler, command_handler};
+pub use shell::{ShellCommand, CommandError};
\ No newline at end of file
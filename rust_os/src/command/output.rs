@@ -0,0 +1,85 @@
+use core::fmt::{self, Write};
+use crate::screen::global::screen_manager;
+use crate::screen::screen::{Color, ColorCode, Writer};
+
+pub const SHELL_SCREEN_ID: usize = 2;
+pub const ERROR_COLOR: ColorCode = ColorCode::new(Color::LightRed, Color::Black);
+
+// Writes to the shell screen, locking the screen manager once per write. The physical
+// screen is refreshed when the output is dropped, or earlier through flush.
+pub struct ShellOutput {
+    dirty: bool,
+    // Colour the screen had before the first set_color, put back by reset_color
+    saved_color: Option<ColorCode>,
+}
+
+impl ShellOutput {
+    pub fn new() -> Self {
+        Self { dirty: false, saved_color: None }
+    }
+
+    // Colours what is written next, until reset_color
+    pub fn set_color(&mut self, color_code: ColorCode) {
+        if let Some(screen) = screen_manager().lock().get_screen_mut(SHELL_SCREEN_ID) {
+            self.saved_color.get_or_insert(screen.color());
+            screen.set_color(color_code);
+        }
+    }
+
+    pub fn reset_color(&mut self) {
+        let Some(color_code) = self.saved_color.take() else {
+            return;
+        };
+        if let Some(screen) = screen_manager().lock().get_screen_mut(SHELL_SCREEN_ID) {
+            screen.set_color(color_code);
+        }
+    }
+
+    // Writes `message` as a line in ERROR_COLOR, for the dispatcher's and the commands'
+    // own failure messages
+    pub fn error(&mut self, message: fmt::Arguments) -> fmt::Result {
+        self.set_color(ERROR_COLOR);
+        let result = writeln!(self, "{}", message);
+        self.reset_color();
+        result
+    }
+
+    pub fn flush(&mut self) {
+        if self.dirty {
+            flush_shell_screen();
+            self.dirty = false;
+        }
+    }
+}
+
+impl Default for ShellOutput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Write for ShellOutput {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut manager = screen_manager().lock();
+        if let Some(screen) = manager.get_screen_mut(SHELL_SCREEN_ID) {
+            let mut writer = Writer::new(screen);
+            writer.write_str(s)?;
+            self.dirty = true;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ShellOutput {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+pub fn flush_shell_screen() {
+    let mut manager = screen_manager().lock();
+    if manager.get_active_screen_id() == SHELL_SCREEN_ID {
+        manager.flush_to_physical();
+        manager.update_cursor();
+    }
+}
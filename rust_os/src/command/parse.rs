@@ -0,0 +1,125 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Width {
+    Byte,
+    Word,
+    Dword,
+}
+
+impl Width {
+    pub fn parse(input: &str) -> Option<Self> {
+        match input {
+            "b" => Some(Width::Byte),
+            "w" => Some(Width::Word),
+            "d" => Some(Width::Dword),
+            _ => None,
+        }
+    }
+
+    pub fn bytes(&self) -> u32 {
+        match self {
+            Width::Byte => 1,
+            Width::Word => 2,
+            Width::Dword => 4,
+        }
+    }
+}
+
+// Accepts decimal, or hexadecimal with a 0x prefix
+pub fn parse_number(input: &str) -> Option<u32> {
+    let (digits, radix) = match input.strip_prefix("0x").or_else(|| input.strip_prefix("0X")) {
+        Some(hex) => (hex, 16),
+        None => (input, 10),
+    };
+
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    u32::from_str_radix(digits, radix).ok()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenizeError {
+    UnterminatedQuote(char),
+    TrailingBackslash,
+}
+
+impl fmt::Display for TokenizeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenizeError::UnterminatedQuote(quote) => write!(f, "missing closing {} quote", quote),
+            TokenizeError::TrailingBackslash => write!(f, "nothing to escape after the final backslash"),
+        }
+    }
+}
+
+// Splits a command line on whitespace, shell style:
+//   'single quotes' keep everything literally
+//   "double quotes" allow \" and \\ inside
+//   a backslash outside quotes escapes the next character
+// Quoted parts join the surrounding word, and "" gives an empty argument.
+pub fn tokenize(input: &str) -> Result<Vec<String>, TokenizeError> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_token = false;
+    let mut chars = input.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if in_token {
+                    tokens.push(mem::take(&mut current));
+                    in_token = false;
+                }
+            }
+            '\'' => {
+                in_token = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => current.push(c),
+                        None => return Err(TokenizeError::UnterminatedQuote('\'')),
+                    }
+                }
+            }
+            '"' => {
+                in_token = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => current.push(c),
+                            Some(c) => {
+                                current.push('\\');
+                                current.push(c);
+                            }
+                            None => return Err(TokenizeError::UnterminatedQuote('"')),
+                        },
+                        Some(c) => current.push(c),
+                        None => return Err(TokenizeError::UnterminatedQuote('"')),
+                    }
+                }
+            }
+            '\\' => {
+                in_token = true;
+                match chars.next() {
+                    Some(c) => current.push(c),
+                    None => return Err(TokenizeError::TrailingBackslash),
+                }
+            }
+            c => {
+                in_token = true;
+                current.push(c);
+            }
+        }
+    }
+
+    if in_token {
+        tokens.push(current);
+    }
+    Ok(tokens)
+}
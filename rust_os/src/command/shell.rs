@@ -0,0 +1,71 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use super::builtins::COMMANDS;
+use super::output::ShellOutput;
+use super::parse::tokenize;
+
+#[derive(Debug, Clone, Copy)]
+pub enum CommandError {
+    // The arguments did not match, the shell prints the command's usage line
+    Usage,
+    Output,
+}
+
+impl From<fmt::Error> for CommandError {
+    fn from(_: fmt::Error) -> Self {
+        CommandError::Output
+    }
+}
+
+// `out` takes the screen lock for each write, so a command must not write to it while it
+// holds the screen manager itself.
+pub trait ShellCommand: Sync {
+    fn name(&self) -> &'static str;
+
+    // Argument synopsis such as "<addr> [b|w|d]", empty for commands without arguments
+    fn usage(&self) -> &'static str {
+        ""
+    }
+
+    fn help(&self) -> &'static str;
+
+    fn run(&self, args: &[&str], out: &mut ShellOutput) -> Result<(), CommandError>;
+}
+
+pub fn commands() -> &'static [&'static dyn ShellCommand] {
+    COMMANDS
+}
+
+pub fn find_command(name: &str) -> Option<&'static dyn ShellCommand> {
+    COMMANDS.iter().copied().find(|command| command.name() == name)
+}
+
+// Parse errors and the dispatcher's own messages go through ShellOutput::error
+pub fn run_command_line(input: &str, out: &mut ShellOutput) {
+    let tokens = match tokenize(input) {
+        Ok(tokens) => tokens,
+        Err(error) => {
+            let _ = out.error(format_args!("Syntax error: {}", error));
+            return;
+        }
+    };
+    let Some((name, rest)) = tokens.split_first() else {
+        return;
+    };
+    let args: Vec<&str> = rest.iter().map(String::as_str).collect();
+
+    match find_command(name) {
+        Some(command) => {
+            if let Err(CommandError::Usage) = command.run(&args, out) {
+                let _ = out.error(format_args!("Usage: {} {}", command.name(), command.usage()));
+            }
+        }
+        None => {
+            let _ = out.error(format_args!(
+                "Unknown command '{}'. Type 'help' for available commands.",
+                name
+            ));
+        }
+    }
+}
@@ -1,12 +1,18 @@
+use core::fmt::Write;
+use core::sync::atomic::{AtomicU8, AtomicU32, AtomicUsize, Ordering};
+use crate::arch::x86::pic::register_irq_handler;
 use crate::arch::x86::port::inb;
 use crate::command::{init_command_handler, command_handler};
+use crate::command::command_handler::PROMPT;
 use crate::screen::global::{init_screen_manager, screen_manager};
-use crate::screen::screen::{BUFFER_HEIGHT, BUFFER_WIDTH, Writer};
+use crate::screen::screen::Writer;
 use crate::printk;
 
 const KEYBOARD_DATA_PORT: u16 = 0x60;
 const KEYBOARD_STATUS_PORT: u16 = 0x64;
 const EXTENDED_KEY_PREFIX: u8 = 0xE0;
+const KEYBOARD_IRQ: u8 = 1;
+const SCANCODE_BUFFER_SIZE: usize = 128;
 
 const SCANCODE_TO_ASCII: [u8; 128] = [
     0,  27, b'1', b'2', b'3', b'4', b'5', b'6',
@@ -37,6 +43,8 @@ const SCANCODE_TO_ASCII: [u8; 128] = [
 #[derive(Clone, Copy, Debug)]
 pub enum KeyEvents {
     Character(char),
+    // A letter typed with Ctrl held, always lowercase
+    Ctrl(char),
     ArrowUp,
     ArrowDown,
     ArrowLeft,
@@ -44,12 +52,61 @@ pub enum KeyEvents {
     BackSpace,
     Delete,
     Enter,
+    Tab,
     Home,
     End,
     SwitchScreenLeft,
     SwitchScreenRight
 }
 
+// Single producer (IRQ1) / single consumer (main loop) ring, so no lock is needed.
+// One slot is always left empty to tell a full ring from an empty one.
+struct ScancodeRing {
+    slots: [AtomicU8; SCANCODE_BUFFER_SIZE],
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+impl ScancodeRing {
+    const fn new() -> Self {
+        Self {
+            slots: [const { AtomicU8::new(0) }; SCANCODE_BUFFER_SIZE],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    fn push(&self, scancode: u8) -> bool {
+        let head = self.head.load(Ordering::Relaxed);
+        let next = (head + 1) % SCANCODE_BUFFER_SIZE;
+        if next == self.tail.load(Ordering::Acquire) {
+            return false;
+        }
+
+        self.slots[head].store(scancode, Ordering::Relaxed);
+        self.head.store(next, Ordering::Release);
+        true
+    }
+
+    fn pop(&self) -> Option<u8> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        if tail == self.head.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let scancode = self.slots[tail].load(Ordering::Relaxed);
+        self.tail.store((tail + 1) % SCANCODE_BUFFER_SIZE, Ordering::Release);
+        Some(scancode)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.tail.load(Ordering::Relaxed) == self.head.load(Ordering::Acquire)
+    }
+}
+
+static SCANCODES: ScancodeRing = ScancodeRing::new();
+static DROPPED_SCANCODES: AtomicU32 = AtomicU32::new(0);
+
 static mut SHIFT_PRESSED: bool = false;
 static mut CTRL_PRESSED: bool = false;
 static mut ALT_PRESSED: bool = false;
@@ -66,9 +123,29 @@ pub fn init_keyboard() {
         ALT_PRESSED = false;
     }
 
+    if !register_irq_handler(KEYBOARD_IRQ, keyboard_irq_handler) {
+        printk!(LogLevel::Error, "Keyboard: IRQ1 is already taken.\n");
+        return;
+    }
+
     printk!(LogLevel::Info, "Keyboard initialized.\n");
 }
 
+fn keyboard_irq_handler() {
+    let scancode = unsafe { inb(KEYBOARD_DATA_PORT) };
+    if !SCANCODES.push(scancode) {
+        DROPPED_SCANCODES.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+pub fn has_pending_scancodes() -> bool {
+    !SCANCODES.is_empty()
+}
+
+pub fn dropped_scancodes() -> u32 {
+    DROPPED_SCANCODES.load(Ordering::Relaxed)
+}
+
 pub fn keyboard_has_data() -> bool {
     unsafe {
         inb(KEYBOARD_STATUS_PORT) & 0x01 != 0
@@ -85,13 +162,9 @@ pub fn reset_keyboard_state() {
 }
 
 pub fn poll_keyboard() -> Option<KeyEvents> {
-    if !keyboard_has_data() {
-        return None;
-    }
+    let scancode = SCANCODES.pop()?;
 
     unsafe {
-        let scancode = inb(KEYBOARD_DATA_PORT);
-        
         if scancode == 0xFF {
             return None;
         }
@@ -172,6 +245,10 @@ fn handle_scancode(key_code: u8, is_extended: bool) -> Option<KeyEvents> {
                 return Some(KeyEvents::BackSpace);
             } else if ascii == b'\n' {
                 return Some(KeyEvents::Enter);
+            } else if ascii == b'\t' {
+                return Some(KeyEvents::Tab);
+            } else if unsafe { CTRL_PRESSED } {
+                return ascii.is_ascii_lowercase().then_some(KeyEvents::Ctrl(ascii as char));
             } else {
                 let mut c = ascii as char;
                 unsafe {
@@ -211,108 +288,12 @@ fn handle_scancode(key_code: u8, is_extended: bool) -> Option<KeyEvents> {
     None
 }
 
-//=====================================================================================================================================
-//                                         CURSOR  MANAGEMENT - REFACTORED TO USE SCREEN MANAGER
-//=====================================================================================================================================
-
-pub fn move_cursor_up() {
-    let mut manager = screen_manager().lock();
-    let active_screen = manager.get_active_screen_mut();
-    let row = active_screen.row_position;
-    if row > 0 {
-        active_screen.set_row_position(row - 1);
-    }
-    manager.update_cursor();
-}
-
-pub fn move_cursor_down() {
-    let mut manager = screen_manager().lock();
-    let active_screen = manager.get_active_screen_mut();
-    let row = active_screen.row_position;
-    if row < BUFFER_HEIGHT - 1 {
-        active_screen.set_row_position(row + 1);
-    }
-    manager.update_cursor();
-}
-
-pub fn move_cursor_left() {
-    let mut manager = screen_manager().lock();
-    let active_screen = manager.get_active_screen_mut();
-    let col = active_screen.column_position;
-    if col > 0 {
-        active_screen.set_column_position(col - 1);
-    }
-    manager.update_cursor();
-}
-
-pub fn move_cursor_right() {
-    let mut manager = screen_manager().lock();
-    let active_screen = manager.get_active_screen_mut();
-    let col = active_screen.column_position;
-    if col < BUFFER_WIDTH - 1 {
-        active_screen.set_column_position(col + 1);
-    }
-    manager.update_cursor();
-}
-
-pub fn write_at_cursor(c: char) {
-    let mut manager = screen_manager().lock();
-    let active_screen = manager.get_active_screen_mut();
-    active_screen.write_byte(c as u8);
-    manager.flush_to_physical();
-    manager.update_cursor();
-}
-
-pub fn move_cursor_home() {
-    let mut manager = screen_manager().lock();
-    let active_screen = manager.get_active_screen_mut();
-    active_screen.set_cursor_position(0, 0);
-    manager.update_cursor();
-}
-
-pub fn move_cursor_end() {
-    let mut manager = screen_manager().lock();
-    let active_screen = manager.get_active_screen_mut();
-    active_screen.set_cursor_position(BUFFER_HEIGHT - 1, BUFFER_WIDTH - 1);
-    manager.update_cursor();
-}
-
-pub fn handle_backspace() {
-    let mut manager = screen_manager().lock();
-    let active_screen = manager.get_active_screen_mut();
-    let col = active_screen.column_position;
-    if col > 0 {
-        active_screen.set_column_position(col - 1);
-        
-        // Store cursor positions to avoid borrowing conflicts
-        let row_pos = active_screen.row_position;
-        let col_pos = active_screen.column_position;
-        
-        active_screen.write_byte_at(row_pos, col_pos, b' ');
-    }
-    manager.flush_to_physical();
-    manager.update_cursor();
-}
-
-pub fn handle_delete() {
-    let mut manager = screen_manager().lock();
-    let active_screen = manager.get_active_screen_mut();
-    
-    // Store cursor positions to avoid borrowing conflicts
-    let row_pos = active_screen.row_position;
-    let col_pos = active_screen.column_position;
-    
-    active_screen.write_byte_at(row_pos, col_pos, b' ');
-    manager.flush_to_physical();
-    manager.update_cursor();
-}
-
 //=====================================================================================================================================
 //                                         LISTEN TO KEYBOARD EVENTS
 //=====================================================================================================================================
 
 pub fn listen_to_keyboard_events() {
-    if let Some(key_event) = poll_keyboard() {
+    while let Some(key_event) = poll_keyboard() {
         match key_event {
             KeyEvents::Character(c) => {
                 let mut manager = screen_manager().lock();
@@ -321,10 +302,14 @@ pub fn listen_to_keyboard_events() {
             }
                 
             KeyEvents::ArrowUp => {
-                move_cursor_up();
+                let mut manager = screen_manager().lock();
+                let mut cmd_handler = command_handler().lock();
+                cmd_handler.history_previous(&mut manager);
             }
             KeyEvents::ArrowDown => {
-                move_cursor_down();
+                let mut manager = screen_manager().lock();
+                let mut cmd_handler = command_handler().lock();
+                cmd_handler.history_next(&mut manager);
             }
             KeyEvents::ArrowLeft => {
                 let mut manager = screen_manager().lock();
@@ -356,21 +341,23 @@ pub fn listen_to_keyboard_events() {
                 let mut cmd_handler = command_handler().lock();
                 cmd_handler.delete_char(&mut manager);
             }
+            KeyEvents::Ctrl(key) => {
+                let mut manager = screen_manager().lock();
+                let mut cmd_handler = command_handler().lock();
+                cmd_handler.handle_ctrl(key, &mut manager);
+            }
+            KeyEvents::Tab => {
+                let mut manager = screen_manager().lock();
+                let mut cmd_handler = command_handler().lock();
+                cmd_handler.complete(&mut manager);
+            }
             
             KeyEvents::Enter => {
-                let mut manager = screen_manager().lock();
-                    
-                if let Some(screen) = manager.get_screen_mut(2) {
-                    let mut writer = Writer::new(screen);
-                    writer.write_byte(b'\n');
-                }
-                    
-                if manager.get_active_screen_id() == 2 {
-                    manager.flush_to_physical();
-                    manager.update_cursor();
+                {
+                    let mut manager = screen_manager().lock();
+                    let mut cmd_handler = command_handler().lock();
+                    cmd_handler.finish_line(&mut manager);
                 }
-                
-                drop(manager);
                     
                 {
                     let mut cmd_handler = command_handler().lock();
@@ -381,8 +368,7 @@ pub fn listen_to_keyboard_events() {
                     let mut manager = screen_manager().lock();
                     if let Some(screen) = manager.get_screen_mut(2) {
                         let mut writer = Writer::new(screen);
-                        writer.write_byte(b'>');
-                        writer.write_byte(b' ');
+                        let _ = writer.write_str(PROMPT);
                             
                         let prompt_row = screen.row_position;
                         let prompt_col = screen.column_position;
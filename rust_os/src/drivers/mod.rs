@@ -1 +1,2 @@
 pub mod keyboard;
+pub mod pit;
\ No newline at end of file
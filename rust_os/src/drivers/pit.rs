@@ -0,0 +1,175 @@
+use crate::arch::x86::cpu::{self, without_interrupts};
+use crate::arch::x86::pic::register_irq_handler;
+use crate::arch::x86::port::outb;
+use crate::printk;
+
+const PIT_CHANNEL0_DATA: u16 = 0x40;
+const PIT_COMMAND: u16 = 0x43;
+const PIT_IRQ: u8 = 0;
+const PIT_BASE_FREQUENCY: u32 = 1_193_182;
+
+// Channel 0, lobyte/hibyte access, mode 2 (rate generator), binary counting
+const PIT_CHANNEL0_RATE_GENERATOR: u8 = 0x34;
+
+pub const DEFAULT_FREQUENCY_HZ: u32 = 1000;
+const MAX_TIMERS: usize = 16;
+
+pub type TimerCallback = fn();
+
+// The generation tells apart the successive timers that reuse the same slot, so a stale id
+// cannot cancel a newer timer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimerId {
+    index: usize,
+    generation: u32,
+}
+
+#[derive(Clone, Copy)]
+struct Timer {
+    generation: u32,
+    deadline: u64,
+    period: u64,
+    callback: TimerCallback,
+}
+
+static mut TICKS: u64 = 0;
+static mut FREQUENCY_HZ: u32 = 0;
+static mut TIMERS: [Option<Timer>; MAX_TIMERS] = [None; MAX_TIMERS];
+static mut GENERATIONS: [u32; MAX_TIMERS] = [0; MAX_TIMERS];
+
+pub fn init_pit(frequency_hz: u32) {
+    // The counter is 16 bits wide, a reload value of 0 stands for 65536
+    let divisor = (PIT_BASE_FREQUENCY / frequency_hz.max(1)).clamp(1, 0x10000);
+    let reload = if divisor == 0x10000 { 0 } else { divisor as u16 };
+
+    without_interrupts(|| unsafe {
+        outb(PIT_COMMAND, PIT_CHANNEL0_RATE_GENERATOR);
+        outb(PIT_CHANNEL0_DATA, (reload & 0xFF) as u8);
+        outb(PIT_CHANNEL0_DATA, (reload >> 8) as u8);
+
+        FREQUENCY_HZ = PIT_BASE_FREQUENCY / divisor;
+        TICKS = 0;
+    });
+
+    if !register_irq_handler(PIT_IRQ, pit_irq_handler) {
+        printk!(LogLevel::Error, "PIT: IRQ0 is already taken.\n");
+        return;
+    }
+
+    printk!(LogLevel::Info, "PIT initialized at {} Hz.\n", frequency());
+}
+
+pub fn frequency() -> u32 {
+    unsafe { FREQUENCY_HZ }
+}
+
+pub fn ticks() -> u64 {
+    without_interrupts(|| unsafe { TICKS })
+}
+
+pub fn uptime_ms() -> u64 {
+    let frequency = frequency() as u64;
+    if frequency == 0 {
+        return 0;
+    }
+    ticks() * 1000 / frequency
+}
+
+// Halts between ticks, so it must be called with interrupts enabled: with them off no tick
+// would ever arrive and the loop would hang.
+pub fn sleep_ms(ms: u64) {
+    if ms == 0 || frequency() == 0 {
+        return;
+    }
+    assert!(cpu::interrupts_enabled(), "sleep_ms called with interrupts disabled");
+
+    let deadline = ticks() + ms_to_ticks(ms);
+    loop {
+        cpu::disable_interrupts();
+        if unsafe { TICKS } >= deadline {
+            cpu::enable_interrupts();
+            break;
+        }
+        cpu::enable_interrupts_and_halt();
+    }
+}
+
+// Callbacks run inside the IRQ0 handler, with interrupts off and before the EOI. They must
+// be short and must not printk or take any lock (screen manager, command handler...) the
+// interrupted code may hold, or the kernel deadlocks. Anything heavier should set a flag
+// for the main loop to act on.
+pub fn add_timer(delay_ms: u64, callback: TimerCallback) -> Option<TimerId> {
+    insert_timer(ms_to_ticks(delay_ms), 0, callback)
+}
+
+// Same rules for the callback as add_timer
+pub fn add_periodic_timer(period_ms: u64, callback: TimerCallback) -> Option<TimerId> {
+    let period = ms_to_ticks(period_ms);
+    insert_timer(period, period, callback)
+}
+
+pub fn cancel_timer(id: TimerId) -> bool {
+    without_interrupts(|| unsafe {
+        let timers = &raw mut TIMERS;
+        match (*timers).get_mut(id.index) {
+            Some(slot) if slot.is_some_and(|timer| timer.generation == id.generation) => {
+                *slot = None;
+                true
+            }
+            _ => false,
+        }
+    })
+}
+
+fn insert_timer(delay: u64, period: u64, callback: TimerCallback) -> Option<TimerId> {
+    without_interrupts(|| unsafe {
+        let timers = &raw mut TIMERS;
+        let index = (*timers).iter().position(|slot| slot.is_none())?;
+        let generations = &raw mut GENERATIONS;
+        let generation = (*generations)[index].wrapping_add(1);
+        (*generations)[index] = generation;
+        (*timers)[index] = Some(Timer {
+            generation,
+            deadline: TICKS + delay,
+            period,
+            callback,
+        });
+        Some(TimerId { index, generation })
+    })
+}
+
+// Rounds up so that a non-zero delay always lasts at least one full tick
+fn ms_to_ticks(ms: u64) -> u64 {
+    let frequency = frequency() as u64;
+    (ms * frequency).div_ceil(1000).max(1)
+}
+
+fn pit_irq_handler() {
+    let now = unsafe {
+        TICKS += 1;
+        TICKS
+    };
+
+    // Slots are copied out before the callback runs, which may add or cancel timers itself
+    for index in 0..MAX_TIMERS {
+        let expired = unsafe {
+            let timers = &raw mut TIMERS;
+            match (*timers)[index] {
+                Some(mut timer) if now >= timer.deadline => {
+                    if timer.period == 0 {
+                        (*timers)[index] = None;
+                    } else {
+                        timer.deadline += timer.period;
+                        (*timers)[index] = Some(timer);
+                    }
+                    Some(timer.callback)
+                }
+                _ => None,
+            }
+        };
+
+        if let Some(callback) = expired {
+            callback();
+        }
+    }
+}
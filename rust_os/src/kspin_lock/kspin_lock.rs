@@ -33,6 +33,19 @@ impl<T> KSpinLock<T> {
 
         KSpinLockGuard { lock: self }
     }
+
+    // Takes the lock only if it is free, for code that must not spin on its own holder
+    pub fn try_lock(&self) -> Option<KSpinLockGuard<'_, T>> {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| KSpinLockGuard { lock: self })
+    }
+
+    // Raw access that ignores the lock, for paths that cannot wait for the holder
+    pub fn data_ptr(&self) -> *mut T {
+        self.value.get()
+    }
 }
 
 impl<T> Deref for KSpinLockGuard<'_, T> {
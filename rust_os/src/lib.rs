@@ -1,35 +1,68 @@
 #![no_std]
 #![no_main]
+#![feature(abi_x86_interrupt)]
+#![feature(alloc_error_handler)]
+
+extern crate alloc;
+
 pub mod drivers;
 pub mod printk;
 pub mod arch;
 pub mod screen;
 pub mod kspin_lock;
 pub mod command;
+pub mod boot;
+pub mod memory;
 
 use core::panic::PanicInfo;
 use crate::drivers::keyboard::{self, listen_to_keyboard_events};
+use crate::drivers::pit;
 use crate::screen::global::{init_screen_manager, screen_manager};
 use crate::screen::screen::Writer;
 use crate::command::{init_command_handler, command_handler};
-// use crate::arch::x86::gdt::{read_gdtr, analyse_gdt_entry};
-// use crate::arch::x86::gdt::read_gdtr;
 use crate::arch::x86::gdt;
+use crate::arch::x86::{cpu, idt, paging, pic};
+use crate::printk::panic::panic_screen;
+use crate::boot::multiboot;
+use crate::memory::{frame, heap, slab};
 
 #[no_mangle]
-pub extern "C" fn kernel_main() -> ! {
-    // gdt::init_gdt();
+pub extern "C" fn kernel_main(multiboot_magic: u32, multiboot_info: u32) -> ! {
+    gdt::init_gdt();
     init_screen_manager();
+    // Before anything that can fault, so early faults reach a handler instead of rebooting
+    idt::init_idt();
+    match multiboot::init_multiboot(multiboot_magic, multiboot_info) {
+        Ok(boot_info) => frame::init_frame_allocator(boot_info),
+        Err(error) => printk!(LogLevel::Error, "Multiboot: {}\n", error),
+    }
+    match paging::init_paging() {
+        Ok(()) => {
+            heap::init_heap();
+            slab::init_slab();
+        }
+        Err(error) => printk!(LogLevel::Error, "Paging: {}\n", error),
+    }
+    pic::init_pic();
+    pit::init_pit(pit::DEFAULT_FREQUENCY_HZ);
     init_command_handler(); 
     
     keyboard::init_keyboard();
+    cpu::enable_interrupts();
 
     loop {
         listen_to_keyboard_events();
+
+        cpu::disable_interrupts();
+        if keyboard::has_pending_scancodes() {
+            cpu::enable_interrupts();
+        } else {
+            cpu::enable_interrupts_and_halt();
+        }
     }
 }
 
 #[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
-    loop {}
+fn panic(info: &PanicInfo) -> ! {
+    panic_screen(info)
 }
\ No newline at end of file
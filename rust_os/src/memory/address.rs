@@ -0,0 +1,18 @@
+// Where the kernel is linked, must match KERNEL_OFFSET in config/linker.ld and boot.asm
+pub const KERNEL_OFFSET: usize = 0xC000_0000;
+
+// boot.asm maps this much physical memory at KERNEL_OFFSET before kernel_main runs
+pub const BOOT_MAPPING_SIZE: usize = 4 * 1024 * 1024;
+
+// Only valid for physical memory in the kernel's linear mapping
+pub fn phys_to_virt(phys: u32) -> usize {
+    phys as usize + KERNEL_OFFSET
+}
+
+pub fn virt_to_phys(virt: usize) -> u32 {
+    (virt - KERNEL_OFFSET) as u32
+}
+
+pub fn is_boot_mapped(phys: u32, size: usize) -> bool {
+    (phys as usize).saturating_add(size) <= BOOT_MAPPING_SIZE
+}
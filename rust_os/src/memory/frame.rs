@@ -0,0 +1,378 @@
+use core::fmt;
+use crate::arch::x86::cpu::without_interrupts;
+use crate::boot::multiboot::{BootInfo, MemoryRegionKind, MAX_MEMORY_REGIONS};
+use crate::kspin_lock::KSpinLock;
+use crate::memory::address::virt_to_phys;
+use crate::printk;
+
+pub const FRAME_SIZE: u32 = 4096;
+
+// One bit per 4 KiB frame of the 32-bit physical address space, a set bit means in use
+const MAX_FRAMES: usize = 1 << 20;
+const BITMAP_WORDS: usize = MAX_FRAMES / 32;
+
+// The BIOS data area, VGA memory and option ROMs all live below 1 MiB
+const LOW_MEMORY_LIMIT: u64 = 0x10_0000;
+const ADDRESS_SPACE_LIMIT: u64 = 1 << 32;
+
+// Defined in config/linker.ld
+extern "C" {
+    static kernel_start: u8;
+    static kernel_end: u8;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PhysFrame(u32);
+
+impl PhysFrame {
+    pub fn containing_address(addr: u32) -> Self {
+        Self(addr & !(FRAME_SIZE - 1))
+    }
+
+    pub fn from_number(number: usize) -> Self {
+        Self((number as u32) * FRAME_SIZE)
+    }
+
+    pub fn start_address(&self) -> u32 {
+        self.0
+    }
+
+    pub fn number(&self) -> usize {
+        (self.0 / FRAME_SIZE) as usize
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum FrameError {
+    Unaligned(u32),
+    NotManaged(u32),
+    DoubleFree(u32),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrameError::Unaligned(addr) => write!(f, "{:#010x} is not frame aligned", addr),
+            FrameError::NotManaged(addr) => write!(f, "{:#010x} is not managed by the allocator", addr),
+            FrameError::DoubleFree(addr) => write!(f, "double free of frame {:#010x}", addr),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameStats {
+    pub total_frames: usize,
+    pub used_frames: usize,
+    pub free_frames: usize,
+    pub allocations: usize,
+    pub frees: usize,
+    pub failed_allocations: usize,
+    pub double_frees: usize,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameOccupancy {
+    pub used: usize,
+    pub free: usize,
+    // Frames the allocator does not hand out: low memory, the kernel image, holes
+    pub reserved: usize,
+}
+
+pub struct FrameAllocator {
+    bitmap: [u32; BITMAP_WORDS],
+    // Usable frame ranges as [start, end) frame numbers, the kernel image excluded
+    regions: [(usize, usize); MAX_MEMORY_REGIONS + 1],
+    region_count: usize,
+    frame_limit: usize,
+    next_free: usize,
+    stats: FrameStats,
+}
+
+static FRAME_ALLOCATOR: KSpinLock<FrameAllocator> = KSpinLock::new(FrameAllocator::new());
+
+impl FrameAllocator {
+    const fn new() -> Self {
+        Self {
+            bitmap: [0; BITMAP_WORDS],
+            regions: [(0, 0); MAX_MEMORY_REGIONS + 1],
+            region_count: 0,
+            frame_limit: 0,
+            next_free: 0,
+            stats: FrameStats {
+                total_frames: 0,
+                used_frames: 0,
+                free_frames: 0,
+                allocations: 0,
+                frees: 0,
+                failed_allocations: 0,
+                double_frees: 0,
+            },
+        }
+    }
+
+    fn init(&mut self, boot_info: &BootInfo) {
+        self.bitmap.fill(u32::MAX);
+        self.region_count = 0;
+        self.frame_limit = 0;
+        self.stats = FrameStats::default();
+
+        let (kernel_first, kernel_last) = kernel_frames();
+
+        for region in boot_info.memory_map() {
+            if region.kind != MemoryRegionKind::Available {
+                continue;
+            }
+
+            // Only whole frames inside the region can be handed out
+            let start = region.base.max(LOW_MEMORY_LIMIT);
+            let end = region.end().min(ADDRESS_SPACE_LIMIT);
+            let first = start.div_ceil(FRAME_SIZE as u64) as usize;
+            let last = (end / FRAME_SIZE as u64) as usize;
+            if first >= last {
+                continue;
+            }
+
+            // The kernel image can split a region in two
+            let below_kernel = (first, last.min(kernel_first));
+            let above_kernel = (first.max(kernel_last), last);
+            for (from, to) in [below_kernel, above_kernel] {
+                if from < to {
+                    self.add_region(from, to);
+                }
+            }
+        }
+
+        self.next_free = self.regions[..self.region_count]
+            .iter()
+            .map(|&(first, _)| first)
+            .min()
+            .unwrap_or(0);
+    }
+
+    fn add_region(&mut self, first: usize, last: usize) {
+        if self.region_count == self.regions.len() {
+            return;
+        }
+        self.regions[self.region_count] = (first, last);
+        self.region_count += 1;
+
+        for frame in first..last {
+            self.clear_bit(frame);
+        }
+        self.frame_limit = self.frame_limit.max(last);
+        self.stats.total_frames += last - first;
+        self.stats.free_frames += last - first;
+    }
+
+    fn is_managed(&self, frame: usize) -> bool {
+        self.regions[..self.region_count]
+            .iter()
+            .any(|&(first, last)| frame >= first && frame < last)
+    }
+
+    fn is_used(&self, frame: usize) -> bool {
+        self.bitmap[frame / 32] & (1 << (frame % 32)) != 0
+    }
+
+    fn set_bit(&mut self, frame: usize) {
+        self.bitmap[frame / 32] |= 1 << (frame % 32);
+    }
+
+    fn clear_bit(&mut self, frame: usize) {
+        self.bitmap[frame / 32] &= !(1 << (frame % 32));
+    }
+
+    fn occupancy(&self, first: usize, last: usize) -> FrameOccupancy {
+        let mut occupancy = FrameOccupancy::default();
+        for &(start, end) in &self.regions[..self.region_count] {
+            let (from, to) = (start.max(first), end.min(last));
+            for frame in from..to {
+                if self.is_used(frame) {
+                    occupancy.used += 1;
+                } else {
+                    occupancy.free += 1;
+                }
+            }
+        }
+        occupancy.reserved = (last - first) - occupancy.used - occupancy.free;
+        occupancy
+    }
+
+    fn find_free(&self, from: usize) -> Option<usize> {
+        let mut frame = from;
+        while frame < self.frame_limit {
+            // Bits below `frame` in its word count as used
+            let word = self.bitmap[frame / 32] | ((1u32 << (frame % 32)) - 1);
+            if word != u32::MAX {
+                let found = (frame / 32) * 32 + word.trailing_ones() as usize;
+                return (found < self.frame_limit).then_some(found);
+            }
+            frame = (frame / 32 + 1) * 32;
+        }
+        None
+    }
+
+    fn allocate(&mut self) -> Option<PhysFrame> {
+        let frame = self.find_free(self.next_free).or_else(|| self.find_free(0));
+
+        match frame {
+            Some(frame) => {
+                self.set_bit(frame);
+                self.next_free = frame + 1;
+                self.stats.allocations += 1;
+                self.stats.used_frames += 1;
+                self.stats.free_frames -= 1;
+                Some(PhysFrame::from_number(frame))
+            }
+            None => {
+                self.stats.failed_allocations += 1;
+                None
+            }
+        }
+    }
+
+    fn allocate_contiguous(&mut self, count: usize) -> Option<PhysFrame> {
+        if count == 0 {
+            return None;
+        }
+
+        let mut start = 0;
+        while let Some(first) = self.find_free(start) {
+            if first + count > self.frame_limit {
+                break;
+            }
+
+            match (first..first + count).find(|&frame| self.is_used(frame)) {
+                Some(used) => start = used + 1,
+                None => {
+                    for frame in first..first + count {
+                        self.set_bit(frame);
+                    }
+                    self.stats.allocations += count;
+                    self.stats.used_frames += count;
+                    self.stats.free_frames -= count;
+                    return Some(PhysFrame::from_number(first));
+                }
+            }
+        }
+
+        self.stats.failed_allocations += 1;
+        None
+    }
+
+    fn deallocate(&mut self, frame: PhysFrame) -> Result<(), FrameError> {
+        let number = frame.number();
+        if !self.is_managed(number) {
+            return Err(FrameError::NotManaged(frame.start_address()));
+        }
+        if !self.is_used(number) {
+            self.stats.double_frees += 1;
+            return Err(FrameError::DoubleFree(frame.start_address()));
+        }
+
+        self.clear_bit(number);
+        self.next_free = self.next_free.min(number);
+        self.stats.frees += 1;
+        self.stats.used_frames -= 1;
+        self.stats.free_frames += 1;
+        Ok(())
+    }
+}
+
+// The image is linked in the higher half, its frames are where the bootloader loaded it
+fn kernel_frames() -> (usize, usize) {
+    let (start, end) = kernel_image_bounds();
+    let start = virt_to_phys(start as usize) as u64;
+    let end = virt_to_phys(end as usize) as u64;
+    (
+        (start / FRAME_SIZE as u64) as usize,
+        end.div_ceil(FRAME_SIZE as u64) as usize,
+    )
+}
+
+pub fn kernel_image_bounds() -> (u32, u32) {
+    (
+        (&raw const kernel_start) as usize as u32,
+        (&raw const kernel_end) as usize as u32,
+    )
+}
+
+pub fn init_frame_allocator(boot_info: &BootInfo) {
+    let stats = without_interrupts(|| {
+        let mut allocator = FRAME_ALLOCATOR.lock();
+        allocator.init(boot_info);
+        allocator.stats
+    });
+
+    let (start, end) = kernel_image_bounds();
+    printk!(
+        LogLevel::Info,
+        "Frame allocator: {} frames free ({} KiB), kernel image {:#010x}-{:#010x}\n",
+        stats.free_frames,
+        stats.free_frames * (FRAME_SIZE as usize / 1024),
+        start,
+        end
+    );
+}
+
+pub fn alloc_frame() -> Option<PhysFrame> {
+    without_interrupts(|| FRAME_ALLOCATOR.lock().allocate())
+}
+
+pub fn alloc_contiguous(count: usize) -> Option<PhysFrame> {
+    without_interrupts(|| FRAME_ALLOCATOR.lock().allocate_contiguous(count))
+}
+
+pub fn free_frame(frame: PhysFrame) -> Result<(), FrameError> {
+    without_interrupts(|| FRAME_ALLOCATOR.lock().deallocate(frame))
+}
+
+pub fn free_contiguous(first: PhysFrame, count: usize) -> Result<(), FrameError> {
+    without_interrupts(|| {
+        let mut allocator = FRAME_ALLOCATOR.lock();
+        for number in first.number()..first.number() + count {
+            allocator.deallocate(PhysFrame::from_number(number))?;
+        }
+        Ok(())
+    })
+}
+
+pub fn free_frame_at(addr: u32) -> Result<(), FrameError> {
+    if addr & (FRAME_SIZE - 1) != 0 {
+        return Err(FrameError::Unaligned(addr));
+    }
+    free_frame(PhysFrame::containing_address(addr))
+}
+
+pub fn frame_stats() -> FrameStats {
+    without_interrupts(|| FRAME_ALLOCATOR.lock().stats)
+}
+
+pub fn is_frame_used(frame: PhysFrame) -> bool {
+    without_interrupts(|| {
+        let allocator = FRAME_ALLOCATOR.lock();
+        frame.number() < MAX_FRAMES && allocator.is_used(frame.number())
+    })
+}
+
+pub fn is_frame_managed(frame: PhysFrame) -> bool {
+    without_interrupts(|| FRAME_ALLOCATOR.lock().is_managed(frame.number()))
+}
+
+// Splits [0, highest managed frame) evenly over `buckets`, returns the frames per bucket
+pub fn frame_occupancy_map(buckets: &mut [FrameOccupancy]) -> usize {
+    without_interrupts(|| {
+        let allocator = FRAME_ALLOCATOR.lock();
+        if buckets.is_empty() {
+            return 0;
+        }
+
+        let per_bucket = allocator.frame_limit.div_ceil(buckets.len()).max(1);
+        for (index, bucket) in buckets.iter_mut().enumerate() {
+            let first = (index * per_bucket).min(allocator.frame_limit);
+            let last = (first + per_bucket).min(allocator.frame_limit);
+            *bucket = allocator.occupancy(first, last);
+        }
+        per_bucket
+    })
+}
@@ -0,0 +1,351 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::mem;
+use core::ptr;
+use crate::arch::x86::cpu::without_interrupts;
+use crate::arch::x86::paging::{self, PageFlags, PAGE_SIZE};
+use crate::kspin_lock::KSpinLock;
+use crate::memory::frame;
+use crate::printk;
+
+pub const HEAP_START: usize = 0xD000_0000;
+pub const HEAP_INITIAL_SIZE: usize = 256 * 1024;
+pub const HEAP_MAX_SIZE: usize = 16 * 1024 * 1024;
+
+// Every block, free or allocated, is a multiple of this and can hold a free list node
+const MIN_BLOCK_SIZE: usize = mem::size_of::<FreeBlock>();
+const BLOCK_ALIGN: usize = mem::align_of::<FreeBlock>();
+
+struct FreeBlock {
+    size: usize,
+    next: *mut FreeBlock,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HeapStats {
+    pub start: usize,
+    pub mapped: usize,
+    pub limit: usize,
+    pub used: usize,
+    pub free: usize,
+    pub allocations: usize,
+    pub frees: usize,
+    pub failed_allocations: usize,
+}
+
+// First fit allocator over a free list kept sorted by address, so that neighbours
+// can be merged back together when a block is freed
+pub struct Heap {
+    start: usize,
+    end: usize,
+    limit: usize,
+    head: *mut FreeBlock,
+    stats: HeapStats,
+}
+
+// The free list only points into the heap's own pages
+unsafe impl Send for Heap {}
+
+static HEAP: KSpinLock<Heap> = KSpinLock::new(Heap::empty());
+
+fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
+
+// Rounds a request to what the free list actually hands out
+pub fn block_size(layout: &Layout) -> usize {
+    align_up(layout.size().max(MIN_BLOCK_SIZE), BLOCK_ALIGN)
+}
+
+impl Heap {
+    const fn empty() -> Self {
+        Self {
+            start: 0,
+            end: 0,
+            limit: 0,
+            head: ptr::null_mut(),
+            stats: HeapStats {
+                start: 0,
+                mapped: 0,
+                limit: 0,
+                used: 0,
+                free: 0,
+                allocations: 0,
+                frees: 0,
+                failed_allocations: 0,
+            },
+        }
+    }
+
+    fn init(&mut self, start: usize, initial_size: usize, max_size: usize) -> bool {
+        self.start = start;
+        self.end = start;
+        self.limit = start + max_size;
+        self.head = ptr::null_mut();
+        self.stats = HeapStats {
+            start,
+            limit: max_size,
+            ..HeapStats::default()
+        };
+        self.grow(initial_size)
+    }
+
+    // Maps at least `size` more bytes at the end of the heap and hands them to the free list
+    fn grow(&mut self, size: usize) -> bool {
+        let size = align_up(size, PAGE_SIZE as usize);
+        if size == 0 || self.end + size > self.limit {
+            return false;
+        }
+
+        let old_end = self.end;
+        while self.end < old_end + size {
+            let Some(frame) = frame::alloc_frame() else {
+                break;
+            };
+            if paging::map_page(self.end as u32, frame, PageFlags::WRITABLE).is_err() {
+                let _ = frame::free_frame(frame);
+                break;
+            }
+            self.end += PAGE_SIZE as usize;
+        }
+
+        if self.end == old_end {
+            return false;
+        }
+        self.stats.mapped += self.end - old_end;
+        self.stats.free += self.end - old_end;
+        unsafe {
+            self.insert_free(old_end, self.end - old_end);
+        }
+        true
+    }
+
+    // Puts [addr, addr + size) back on the list, merging it with the blocks around it
+    unsafe fn insert_free(&mut self, addr: usize, size: usize) {
+        let mut prev: *mut FreeBlock = ptr::null_mut();
+        let mut next = self.head;
+        while !next.is_null() && (next as usize) < addr {
+            prev = next;
+            next = (*next).next;
+        }
+
+        let block = addr as *mut FreeBlock;
+        block.write(FreeBlock { size, next });
+
+        if !next.is_null() && addr + size == next as usize {
+            (*block).size += (*next).size;
+            (*block).next = (*next).next;
+        }
+
+        if prev.is_null() {
+            self.head = block;
+        } else if prev as usize + (*prev).size == addr {
+            (*prev).size += (*block).size;
+            (*prev).next = (*block).next;
+        } else {
+            (*prev).next = block;
+        }
+    }
+
+    fn free_list_contains(&self, addr: usize) -> bool {
+        let mut block = self.head;
+        unsafe {
+            while !block.is_null() && (block as usize) <= addr {
+                if addr < block as usize + (*block).size {
+                    return true;
+                }
+                block = (*block).next;
+            }
+        }
+        false
+    }
+
+    unsafe fn find_fit(&mut self, size: usize, align: usize) -> Option<usize> {
+        let mut prev: *mut FreeBlock = ptr::null_mut();
+        let mut current = self.head;
+
+        while !current.is_null() {
+            let block_start = current as usize;
+            let block_end = block_start + (*current).size;
+
+            // Padding in front of an aligned block must be big enough to stay on the list
+            let mut alloc_start = align_up(block_start, align);
+            if alloc_start != block_start && alloc_start - block_start < MIN_BLOCK_SIZE {
+                alloc_start = align_up(block_start + MIN_BLOCK_SIZE, align);
+            }
+            let alloc_end = alloc_start + size;
+            let back = block_end.saturating_sub(alloc_end);
+
+            if alloc_end <= block_end && (back == 0 || back >= MIN_BLOCK_SIZE) {
+                let next = (*current).next;
+                if prev.is_null() {
+                    self.head = next;
+                } else {
+                    (*prev).next = next;
+                }
+
+                if alloc_start != block_start {
+                    self.insert_free(block_start, alloc_start - block_start);
+                }
+                if back != 0 {
+                    self.insert_free(alloc_end, back);
+                }
+                return Some(alloc_start);
+            }
+
+            prev = current;
+            current = (*current).next;
+        }
+        None
+    }
+
+    // Unmaps `size` bytes below the break, which only works while they are one free block
+    fn shrink(&mut self, size: usize) -> bool {
+        let size = align_up(size, PAGE_SIZE as usize);
+        if size == 0 || size > self.end - self.start {
+            return false;
+        }
+
+        unsafe {
+            let mut prev: *mut FreeBlock = ptr::null_mut();
+            let mut last = self.head;
+            while !last.is_null() && !(*last).next.is_null() {
+                prev = last;
+                last = (*last).next;
+            }
+            if last.is_null() || last as usize + (*last).size != self.end || (*last).size < size {
+                return false;
+            }
+
+            let remaining = (*last).size - size;
+            if remaining != 0 && remaining < MIN_BLOCK_SIZE {
+                return false;
+            }
+            if remaining != 0 {
+                (*last).size = remaining;
+            } else if prev.is_null() {
+                self.head = ptr::null_mut();
+            } else {
+                (*prev).next = ptr::null_mut();
+            }
+        }
+
+        let new_end = self.end - size;
+        while self.end > new_end {
+            self.end -= PAGE_SIZE as usize;
+            if let Ok(frame) = paging::unmap_page(self.end as u32) {
+                let _ = frame::free_frame(frame);
+            }
+        }
+        self.stats.mapped -= size;
+        self.stats.free -= size;
+        true
+    }
+
+    fn move_break(&mut self, increment: isize) -> Option<usize> {
+        let moved = match increment {
+            0 => true,
+            increment if increment > 0 => self.grow(increment as usize),
+            increment => self.shrink(increment.unsigned_abs()),
+        };
+        moved.then_some(self.end)
+    }
+
+    fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let size = block_size(&layout);
+        let align = layout.align().max(BLOCK_ALIGN);
+
+        let mut found = unsafe { self.find_fit(size, align) };
+        if found.is_none() && self.grow(size + align) {
+            found = unsafe { self.find_fit(size, align) };
+        }
+
+        match found {
+            Some(addr) => {
+                self.stats.allocations += 1;
+                self.stats.used += size;
+                self.stats.free -= size;
+                addr as *mut u8
+            }
+            None => {
+                self.stats.failed_allocations += 1;
+                ptr::null_mut()
+            }
+        }
+    }
+
+    unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        let size = block_size(&layout);
+        self.insert_free(ptr as usize, size);
+        self.stats.frees += 1;
+        self.stats.used -= size;
+        self.stats.free += size;
+    }
+}
+
+pub struct KernelAllocator;
+
+unsafe impl GlobalAlloc for KernelAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        without_interrupts(|| HEAP.lock().allocate(layout))
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        without_interrupts(|| HEAP.lock().deallocate(ptr, layout))
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: KernelAllocator = KernelAllocator;
+
+#[alloc_error_handler]
+fn alloc_error(layout: Layout) -> ! {
+    panic!(
+        "kernel heap exhausted: {} bytes aligned to {} could not be allocated",
+        layout.size(),
+        layout.align()
+    );
+}
+
+pub fn init_heap() {
+    let ready = without_interrupts(|| HEAP.lock().init(HEAP_START, HEAP_INITIAL_SIZE, HEAP_MAX_SIZE));
+
+    if ready {
+        let stats = heap_stats();
+        printk!(
+            LogLevel::Info,
+            "Heap: {} KiB mapped at {:#010x}, grows up to {} KiB\n",
+            stats.mapped / 1024,
+            stats.start,
+            stats.limit / 1024
+        );
+    } else {
+        printk!(LogLevel::Error, "Heap: could not map the initial heap pages\n");
+    }
+}
+
+pub fn heap_stats() -> HeapStats {
+    without_interrupts(|| HEAP.lock().stats)
+}
+
+pub fn heap_break() -> usize {
+    without_interrupts(|| HEAP.lock().end)
+}
+
+// Moves the end of the mapped heap, page by page, and returns the new break. Pages gained
+// this way join the free list, pages can only be released while nothing uses them.
+pub fn move_heap_break(increment: isize) -> Option<usize> {
+    without_interrupts(|| HEAP.lock().move_break(increment))
+}
+
+pub fn heap_contains(addr: usize) -> bool {
+    without_interrupts(|| {
+        let heap = HEAP.lock();
+        addr >= heap.start && addr < heap.end
+    })
+}
+
+// Whether `addr` lies in a block currently on the free list, which is how a double free
+// shows up since freeing overwrites the start of the block with a list node
+pub fn heap_is_free(addr: usize) -> bool {
+    without_interrupts(|| HEAP.lock().free_list_contains(addr))
+}
@@ -0,0 +1,128 @@
+use alloc::alloc::{alloc, dealloc};
+use core::alloc::Layout;
+use core::mem;
+use core::ptr;
+use crate::memory::heap;
+use crate::printk;
+
+const KMALLOC_ALIGN: usize = 8;
+const ALLOCATED_MAGIC: u32 = 0x6B6D_616C;
+
+// Sits right in front of every block so kfree and ksize only need the pointer
+#[repr(C, align(8))]
+struct Header {
+    magic: u32,
+    // Bytes taken from the heap, header included
+    total: u32,
+}
+
+const HEADER_SIZE: usize = mem::size_of::<Header>();
+
+fn layout_for(total: usize) -> Option<Layout> {
+    Layout::from_size_align(total, KMALLOC_ALIGN).ok()
+}
+
+unsafe fn header_of(ptr: *const u8, caller: &str) -> Option<*mut Header> {
+    let addr = ptr as usize;
+    if addr & (KMALLOC_ALIGN - 1) != 0 || addr < HEADER_SIZE || !heap::heap_contains(addr - HEADER_SIZE) {
+        printk!(LogLevel::Error, "{}: {:p} does not point into the kernel heap\n", caller, ptr);
+        return None;
+    }
+
+    // A freed block starts with a free list node instead of the header, so the list itself
+    // is asked rather than trusting whatever the magic slot now holds
+    let header = (addr - HEADER_SIZE) as *mut Header;
+    if heap::heap_is_free(header as usize) {
+        printk!(LogLevel::Error, "{}: {:p} was already freed\n", caller, ptr);
+        return None;
+    }
+    if (*header).magic != ALLOCATED_MAGIC {
+        printk!(LogLevel::Error, "{}: {:p} was not returned by kmalloc\n", caller, ptr);
+        return None;
+    }
+    Some(header)
+}
+
+// Virtually contiguous memory from the kernel heap, 8 byte aligned. The heap is mapped page
+// by page, so the frames behind a block are not contiguous and it must not be handed to a
+// device for DMA. Returns null when `size` is 0 or the heap cannot grow any further.
+pub fn kmalloc(size: usize) -> *mut u8 {
+    if size == 0 {
+        return ptr::null_mut();
+    }
+
+    let layout = match size.checked_add(HEADER_SIZE).and_then(layout_for) {
+        Some(layout) if layout.size() <= u32::MAX as usize => layout,
+        _ => {
+            printk!(LogLevel::Error, "kmalloc: {} bytes is too large a request\n", size);
+            return ptr::null_mut();
+        }
+    };
+
+    unsafe {
+        let block = alloc(layout);
+        if block.is_null() {
+            printk!(LogLevel::Error, "kmalloc: out of memory allocating {} bytes\n", size);
+            return ptr::null_mut();
+        }
+
+        (block as *mut Header).write(Header {
+            magic: ALLOCATED_MAGIC,
+            total: layout.size() as u32,
+        });
+        block.add(HEADER_SIZE)
+    }
+}
+
+/// Returns a kmalloc block to the heap. Bad pointers are reported instead of corrupting
+/// the heap whenever the header gives them away.
+///
+/// # Safety
+///
+/// `ptr` must be null or a pointer returned by kmalloc, and neither it nor any copy of it
+/// may be used once it has been freed.
+pub unsafe fn kfree(ptr: *mut u8) {
+    if ptr.is_null() {
+        return;
+    }
+
+    if let Some(header) = header_of(ptr, "kfree") {
+        let total = (*header).total as usize;
+        if let Some(layout) = layout_for(total) {
+            dealloc(header as *mut u8, layout);
+        }
+    }
+}
+
+/// Usable bytes behind `ptr`, which can be more than were asked for since blocks are
+/// rounded up. 0 is returned for null or bad pointers.
+///
+/// # Safety
+///
+/// `ptr` must be null or a pointer returned by kmalloc that has not been freed yet.
+pub unsafe fn ksize(ptr: *const u8) -> usize {
+    if ptr.is_null() {
+        return 0;
+    }
+
+    match header_of(ptr, "ksize").and_then(|header| layout_for((*header).total as usize)) {
+        Some(layout) => heap::block_size(&layout) - HEADER_SIZE,
+        None => 0,
+    }
+}
+
+// Moves the heap break by `increment` bytes, rounded to whole pages, and returns the new
+// break. Unlike sbrk no memory is handed to the caller: grown pages join the kmalloc free
+// list, and shrinking only releases pages nothing is using.
+pub fn kbrk(increment: isize) -> Option<usize> {
+    let new_break = heap::move_heap_break(increment);
+    if new_break.is_none() {
+        printk!(
+            LogLevel::Error,
+            "kbrk: cannot move the heap break {:#010x} by {} bytes\n",
+            heap::heap_break(),
+            increment
+        );
+    }
+    new_break
+}
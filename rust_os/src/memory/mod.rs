@@ -0,0 +1,6 @@
+pub mod address;
+pub mod frame;
+pub mod heap;
+pub mod kmalloc;
+pub mod slab;
+pub mod vmalloc;
\ No newline at end of file
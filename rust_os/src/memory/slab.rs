@@ -0,0 +1,458 @@
+use alloc::vec::Vec;
+use core::mem;
+use core::ptr;
+use crate::arch::x86::cpu::without_interrupts;
+use crate::arch::x86::paging::{self, PageFlags, PAGE_SIZE};
+use crate::kspin_lock::KSpinLock;
+use crate::memory::frame;
+use crate::printk;
+
+// Slab pages get their own window so a page address is enough to find its header
+pub const SLAB_START: usize = 0xF000_0000;
+pub const SLAB_END: usize = 0xF800_0000;
+
+const PAGE: usize = PAGE_SIZE as usize;
+const MAX_CACHES: usize = 32;
+const MAX_RECYCLED_PAGES: usize = 256;
+// Empty slabs kept around per cache before pages go back to the frame allocator
+const MAX_EMPTY_SLABS: usize = 1;
+
+pub type Constructor = fn(*mut u8);
+
+struct FreeObject {
+    next: *mut FreeObject,
+}
+
+// Lives at the start of every slab page, the objects follow it
+struct Slab {
+    cache: *const KmemCache,
+    next: *mut Slab,
+    free: *mut FreeObject,
+    in_use: usize,
+}
+
+struct CacheState {
+    // Slabs with at least one free object, empty ones included
+    partial: *mut Slab,
+    full: *mut Slab,
+    slabs: usize,
+    empty_slabs: usize,
+    active_objects: usize,
+    allocations: usize,
+    frees: usize,
+    failed_allocations: usize,
+}
+
+unsafe impl Send for CacheState {}
+
+pub struct KmemCache {
+    name: &'static str,
+    object_size: usize,
+    align: usize,
+    constructor: Option<Constructor>,
+    state: KSpinLock<CacheState>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct KmemCacheStats {
+    pub name: &'static str,
+    pub object_size: usize,
+    pub stride: usize,
+    pub objects_per_slab: usize,
+    pub slabs: usize,
+    pub active_objects: usize,
+    pub total_objects: usize,
+    pub allocations: usize,
+    pub frees: usize,
+    pub failed_allocations: usize,
+}
+
+struct SlabPages {
+    next: usize,
+    // Unmapped pages from released slabs, handed out again before `next` moves
+    recycled: [usize; MAX_RECYCLED_PAGES],
+    recycled_len: usize,
+}
+
+static SLAB_PAGES: KSpinLock<SlabPages> = KSpinLock::new(SlabPages {
+    next: SLAB_START,
+    recycled: [0; MAX_RECYCLED_PAGES],
+    recycled_len: 0,
+});
+
+static CACHES: KSpinLock<[Option<&'static KmemCache>; MAX_CACHES]> = KSpinLock::new([None; MAX_CACHES]);
+
+const fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
+
+fn map_slab_page() -> Option<usize> {
+    let mut pages = SLAB_PAGES.lock();
+    let recycled = pages.recycled_len > 0;
+    let page = if recycled {
+        pages.recycled_len -= 1;
+        pages.recycled[pages.recycled_len]
+    } else if pages.next < SLAB_END {
+        pages.next += PAGE;
+        pages.next - PAGE
+    } else {
+        return None;
+    };
+
+    let mapped = frame::alloc_frame().map(|frame| {
+        let result = paging::map_page(page as u32, frame, PageFlags::WRITABLE);
+        if result.is_err() {
+            let _ = frame::free_frame(frame);
+        }
+        result.is_ok()
+    });
+
+    if mapped == Some(true) {
+        return Some(page);
+    }
+
+    // Nothing was mapped, give the address back to where it came from
+    if recycled {
+        pages.recycled_len += 1;
+    } else {
+        pages.next -= PAGE;
+    }
+    None
+}
+
+fn unmap_slab_page(page: usize) {
+    if let Ok(frame) = paging::unmap_page(page as u32) {
+        let _ = frame::free_frame(frame);
+    }
+
+    let mut pages = SLAB_PAGES.lock();
+    if pages.recycled_len < MAX_RECYCLED_PAGES {
+        let index = pages.recycled_len;
+        pages.recycled[index] = page;
+        pages.recycled_len += 1;
+    }
+}
+
+unsafe fn remove_slab(list: &mut *mut Slab, slab: *mut Slab) {
+    let mut link: *mut *mut Slab = list;
+    while !(*link).is_null() {
+        if *link == slab {
+            *link = (*slab).next;
+            return;
+        }
+        link = &raw mut (**link).next;
+    }
+}
+
+impl KmemCache {
+    pub const fn new(name: &'static str, size: usize, align: usize) -> Self {
+        assert!(align.is_power_of_two(), "slab alignment must be a power of two");
+        Self {
+            name,
+            object_size: size,
+            align,
+            constructor: None,
+            state: KSpinLock::new(CacheState {
+                partial: ptr::null_mut(),
+                full: ptr::null_mut(),
+                slabs: 0,
+                empty_slabs: 0,
+                active_objects: 0,
+                allocations: 0,
+                frees: 0,
+                failed_allocations: 0,
+            }),
+        }
+    }
+
+    // Runs once on every object when its slab is created, not on each alloc. Objects are
+    // expected to be handed back to free in their constructed state.
+    pub const fn with_constructor(mut self, constructor: Constructor) -> Self {
+        self.constructor = Some(constructor);
+        self
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    // Lists the cache in slabinfo. Caches are statics built by the const `new`, so their
+    // owner calls this from its init function.
+    pub fn register(&'static self) {
+        let registered = without_interrupts(|| {
+            let mut caches = CACHES.lock();
+            if caches.iter().flatten().any(|cache| ptr::eq(*cache, self)) {
+                return true;
+            }
+            match caches.iter_mut().find(|slot| slot.is_none()) {
+                Some(slot) => {
+                    *slot = Some(self);
+                    true
+                }
+                None => false,
+            }
+        });
+
+        if !registered {
+            printk!(LogLevel::Warn, "slab {}: registry full, hidden from slabinfo\n", self.name);
+        }
+    }
+
+    // The free list link normally reuses the first word of a free object. With a
+    // constructor it goes after the object instead, so constructed state survives.
+    fn link_offset(&self) -> usize {
+        if self.constructor.is_some() {
+            align_up(self.object_size, mem::align_of::<FreeObject>())
+        } else {
+            0
+        }
+    }
+
+    fn stride(&self) -> usize {
+        let size = self.object_size.max(self.link_offset() + mem::size_of::<FreeObject>());
+        align_up(size, self.align.max(mem::align_of::<FreeObject>()))
+    }
+
+    fn link_of(&self, object: *mut u8) -> *mut FreeObject {
+        (object as usize + self.link_offset()) as *mut FreeObject
+    }
+
+    fn object_of(&self, link: *mut FreeObject) -> *mut u8 {
+        (link as usize - self.link_offset()) as *mut u8
+    }
+
+    fn first_object_offset(&self) -> usize {
+        align_up(mem::size_of::<Slab>(), self.align.max(mem::align_of::<FreeObject>()))
+    }
+
+    fn objects_per_slab(&self) -> usize {
+        PAGE.saturating_sub(self.first_object_offset()) / self.stride()
+    }
+
+    unsafe fn create_slab(&self) -> Option<*mut Slab> {
+        let page = map_slab_page()?;
+        let slab = page as *mut Slab;
+        slab.write(Slab {
+            cache: self,
+            next: ptr::null_mut(),
+            free: ptr::null_mut(),
+            in_use: 0,
+        });
+
+        // Thread the free list backwards so objects come out in address order
+        let first = page + self.first_object_offset();
+        for index in (0..self.objects_per_slab()).rev() {
+            let object = (first + index * self.stride()) as *mut u8;
+            if let Some(constructor) = self.constructor {
+                constructor(object);
+            }
+            let link = self.link_of(object);
+            (*link).next = (*slab).free;
+            (*slab).free = link;
+        }
+        Some(slab)
+    }
+
+    // Returns null when the object cannot fit in a page or no memory is left
+    pub fn alloc(&'static self) -> *mut u8 {
+        if self.objects_per_slab() == 0 {
+            printk!(
+                LogLevel::Error,
+                "slab {}: {} byte objects do not fit in a slab page\n",
+                self.name,
+                self.object_size
+            );
+            return ptr::null_mut();
+        }
+
+        let object = without_interrupts(|| unsafe {
+            let mut state = self.state.lock();
+            if state.partial.is_null() {
+                match self.create_slab() {
+                    Some(slab) => {
+                        state.partial = slab;
+                        state.slabs += 1;
+                        state.empty_slabs += 1;
+                    }
+                    None => {
+                        state.failed_allocations += 1;
+                        return ptr::null_mut();
+                    }
+                }
+            }
+
+            let slab = state.partial;
+            let link = (*slab).free;
+            (*slab).free = (*link).next;
+            if (*slab).in_use == 0 {
+                state.empty_slabs -= 1;
+            }
+            (*slab).in_use += 1;
+
+            if (*slab).free.is_null() {
+                state.partial = (*slab).next;
+                (*slab).next = state.full;
+                state.full = slab;
+            }
+
+            state.active_objects += 1;
+            state.allocations += 1;
+            self.object_of(link)
+        });
+
+        if object.is_null() {
+            printk!(LogLevel::Error, "slab {}: out of memory\n", self.name);
+        }
+        object
+    }
+
+    /// Hands `object` back to its slab. Objects from another cache or not on an object
+    /// boundary are reported and left alone.
+    ///
+    /// # Safety
+    ///
+    /// `object` must be null or a pointer returned by this cache's alloc, and must not be
+    /// used once freed. With a constructor, it must also be back in its constructed state.
+    pub unsafe fn free(&self, object: *mut u8) {
+        if object.is_null() {
+            return;
+        }
+
+        let addr = object as usize;
+        let slab = (addr & !(PAGE - 1)) as *mut Slab;
+        let first = slab as usize + self.first_object_offset();
+        let valid = (SLAB_START..SLAB_END).contains(&addr)
+            && paging::translate(slab as u32).is_some()
+            && ptr::eq((*slab).cache, self)
+            && addr >= first
+            && (addr - first).is_multiple_of(self.stride())
+            && (addr - first) / self.stride() < self.objects_per_slab();
+        if !valid {
+            printk!(LogLevel::Error, "slab {}: {:p} is not one of its objects\n", self.name, object);
+            return;
+        }
+
+        let released = without_interrupts(|| {
+            let mut state = self.state.lock();
+            let link = self.link_of(object);
+            let mut free = (*slab).free;
+            while !free.is_null() {
+                if free == link {
+                    printk!(LogLevel::Error, "slab {}: double free of {:p}\n", self.name, object);
+                    return false;
+                }
+                free = (*free).next;
+            }
+
+            if (*slab).free.is_null() {
+                remove_slab(&mut state.full, slab);
+                (*slab).next = state.partial;
+                state.partial = slab;
+            }
+
+            (*link).next = (*slab).free;
+            (*slab).free = link;
+            (*slab).in_use -= 1;
+            state.active_objects -= 1;
+            state.frees += 1;
+
+            if (*slab).in_use != 0 {
+                return false;
+            }
+            if state.empty_slabs < MAX_EMPTY_SLABS {
+                state.empty_slabs += 1;
+                return false;
+            }
+            remove_slab(&mut state.partial, slab);
+            state.slabs -= 1;
+            true
+        });
+
+        if released {
+            unmap_slab_page(slab as usize);
+        }
+    }
+
+    // Gives every empty slab back, returns how many pages were released
+    pub fn shrink(&self) -> usize {
+        let mut released = 0;
+        loop {
+            let slab = without_interrupts(|| unsafe {
+                let mut state = self.state.lock();
+                let mut slab = state.partial;
+                while !slab.is_null() && (*slab).in_use != 0 {
+                    slab = (*slab).next;
+                }
+                if !slab.is_null() {
+                    remove_slab(&mut state.partial, slab);
+                    state.slabs -= 1;
+                    state.empty_slabs -= 1;
+                }
+                slab
+            });
+
+            if slab.is_null() {
+                return released;
+            }
+            unmap_slab_page(slab as usize);
+            released += 1;
+        }
+    }
+
+    pub fn stats(&self) -> KmemCacheStats {
+        let state = without_interrupts(|| {
+            let state = self.state.lock();
+            (state.slabs, state.active_objects, state.allocations, state.frees, state.failed_allocations)
+        });
+        let (slabs, active_objects, allocations, frees, failed_allocations) = state;
+
+        KmemCacheStats {
+            name: self.name,
+            object_size: self.object_size,
+            stride: self.stride(),
+            objects_per_slab: self.objects_per_slab(),
+            slabs,
+            active_objects,
+            total_objects: slabs * self.objects_per_slab(),
+            allocations,
+            frees,
+            failed_allocations,
+        }
+    }
+}
+
+// Exercised once at boot, so a broken slab allocator shows up in the log rather than in
+// whatever uses it first. It stays registered and visible in slabinfo.
+static SELFTEST_CACHE: KmemCache = KmemCache::new("slab_selftest", 48, 16);
+const SELFTEST_OBJECTS: usize = 8;
+
+pub fn init_slab() {
+    SELFTEST_CACHE.register();
+
+    let mut objects = [ptr::null_mut(); SELFTEST_OBJECTS];
+    let mut ok = true;
+    for index in 0..SELFTEST_OBJECTS {
+        let object = SELFTEST_CACHE.alloc();
+        ok &= !object.is_null()
+            && (object as usize).is_multiple_of(16)
+            && !objects[..index].contains(&object);
+        objects[index] = object;
+    }
+    for &object in &objects {
+        unsafe { SELFTEST_CACHE.free(object) };
+    }
+    ok &= SELFTEST_CACHE.stats().active_objects == 0;
+    SELFTEST_CACHE.shrink();
+
+    if ok {
+        printk!(LogLevel::Info, "Slab: self-test passed\n");
+    } else {
+        printk!(LogLevel::Error, "Slab: self-test failed\n");
+    }
+}
+
+// Only registered caches show up here
+pub fn cache_stats() -> Vec<KmemCacheStats> {
+    let caches = without_interrupts(|| *CACHES.lock());
+    caches.iter().flatten().map(|cache| cache.stats()).collect()
+}
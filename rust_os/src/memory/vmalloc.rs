@@ -0,0 +1,298 @@
+use core::fmt;
+use core::ptr;
+use crate::arch::x86::cpu::without_interrupts;
+use crate::arch::x86::paging::{self, PageFlags, PAGE_SIZE};
+use crate::kspin_lock::KSpinLock;
+use crate::memory::frame;
+use crate::printk;
+
+pub const VMALLOC_START: usize = 0xE000_0000;
+pub const VMALLOC_END: usize = 0xF000_0000;
+const MAX_AREAS: usize = 64;
+const PAGE: usize = PAGE_SIZE as usize;
+
+#[derive(Clone, Copy, Debug)]
+struct VmArea {
+    start: usize,
+    pages: usize,
+    // Areas grown through vbrk sit right against the break and get no guard page
+    from_break: bool,
+}
+
+impl VmArea {
+    fn end(&self) -> usize {
+        self.start + self.pages * PAGE
+    }
+
+    // An unmapped page is left after each vmalloc area so overruns fault
+    fn span_end(&self) -> usize {
+        if self.from_break {
+            self.end()
+        } else {
+            self.end() + PAGE
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum VmallocError {
+    TooManyAreas,
+    OutOfVirtualSpace,
+    OutOfMemory,
+    NotAllocated(usize),
+    BreakInUse,
+}
+
+impl fmt::Display for VmallocError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VmallocError::TooManyAreas => write!(f, "all {} areas are in use", MAX_AREAS),
+            VmallocError::OutOfVirtualSpace => write!(f, "the vmalloc zone is full"),
+            VmallocError::OutOfMemory => write!(f, "out of physical frames"),
+            VmallocError::NotAllocated(addr) => write!(f, "{:#010x} was not returned by vmalloc", addr),
+            VmallocError::BreakInUse => write!(f, "the memory below the break belongs to vmalloc areas"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct VmallocStats {
+    pub areas: usize,
+    pub mapped: usize,
+    pub brk: usize,
+}
+
+struct Vmalloc {
+    areas: [Option<VmArea>; MAX_AREAS],
+    // Everything handed out so far lies in [VMALLOC_START, brk)
+    brk: usize,
+}
+
+static VMALLOC: KSpinLock<Vmalloc> = KSpinLock::new(Vmalloc {
+    areas: [None; MAX_AREAS],
+    brk: VMALLOC_START,
+});
+
+// Each page gets its own frame, so the range is only contiguous in virtual memory
+fn map_pages(start: usize, pages: usize) -> Result<(), VmallocError> {
+    for page in 0..pages {
+        let addr = start + page * PAGE;
+        let mapped = frame::alloc_frame().map(|frame| {
+            let result = paging::map_page(addr as u32, frame, PageFlags::WRITABLE);
+            if result.is_err() {
+                let _ = frame::free_frame(frame);
+            }
+            result.is_ok()
+        });
+
+        if mapped != Some(true) {
+            unmap_pages(start, page);
+            return Err(VmallocError::OutOfMemory);
+        }
+    }
+    Ok(())
+}
+
+fn unmap_pages(start: usize, pages: usize) {
+    for page in 0..pages {
+        if let Ok(frame) = paging::unmap_page((start + page * PAGE) as u32) {
+            let _ = frame::free_frame(frame);
+        }
+    }
+}
+
+impl Vmalloc {
+    fn live_areas(&self) -> impl Iterator<Item = &VmArea> {
+        self.areas.iter().flatten()
+    }
+
+    fn free_slot(&self) -> Result<usize, VmallocError> {
+        self.areas
+            .iter()
+            .position(Option::is_none)
+            .ok_or(VmallocError::TooManyAreas)
+    }
+
+    fn find_area(&self, addr: usize) -> Option<usize> {
+        self.areas
+            .iter()
+            .position(|area| matches!(area, Some(area) if area.start == addr))
+    }
+
+    fn top_area(&self) -> Option<usize> {
+        self.areas
+            .iter()
+            .position(|area| matches!(area, Some(area) if area.span_end() == self.brk))
+    }
+
+    // Lowest gap below the break that `span` bytes fit in, freed areas leave such gaps
+    fn find_gap(&self, span: usize) -> Option<usize> {
+        let overlaps = |start: usize| {
+            self.live_areas()
+                .any(|area| start < area.span_end() && area.start < start + span)
+        };
+
+        core::iter::once(VMALLOC_START)
+            .chain(self.live_areas().map(VmArea::span_end))
+            .filter(|&start| start + span <= self.brk && !overlaps(start))
+            .min()
+    }
+
+    fn lower_break(&mut self) {
+        self.brk = self
+            .live_areas()
+            .map(VmArea::span_end)
+            .max()
+            .unwrap_or(VMALLOC_START);
+    }
+
+    fn allocate(&mut self, size: usize) -> Result<usize, VmallocError> {
+        if size > VMALLOC_END - VMALLOC_START {
+            return Err(VmallocError::OutOfVirtualSpace);
+        }
+        let slot = self.free_slot()?;
+        let pages = size.div_ceil(PAGE);
+        let span = (pages + 1) * PAGE;
+
+        let start = match self.find_gap(span) {
+            Some(start) => start,
+            None if span <= VMALLOC_END - self.brk => self.brk,
+            None => return Err(VmallocError::OutOfVirtualSpace),
+        };
+
+        map_pages(start, pages)?;
+        self.areas[slot] = Some(VmArea { start, pages, from_break: false });
+        self.brk = self.brk.max(start + span);
+        Ok(start)
+    }
+
+    fn free(&mut self, addr: usize) -> Result<(), VmallocError> {
+        let slot = self.find_area(addr).ok_or(VmallocError::NotAllocated(addr))?;
+        if let Some(area) = self.areas[slot].take() {
+            unmap_pages(area.start, area.pages);
+        }
+        self.lower_break();
+        Ok(())
+    }
+
+    fn size(&self, addr: usize) -> Option<usize> {
+        self.find_area(addr)
+            .and_then(|slot| self.areas[slot])
+            .map(|area| area.pages * PAGE)
+    }
+
+    // The break owns one area at the top of the zone, grown and shrunk in place so the
+    // memory it covers stays contiguous like sbrk memory
+    fn move_break(&mut self, increment: isize) -> Result<usize, VmallocError> {
+        let old_break = self.brk;
+        let pages = increment.unsigned_abs().div_ceil(PAGE);
+        if pages == 0 {
+            return Ok(old_break);
+        }
+
+        let top = self
+            .top_area()
+            .filter(|&slot| matches!(self.areas[slot], Some(area) if area.from_break));
+
+        if increment > 0 {
+            if pages * PAGE > VMALLOC_END - old_break {
+                return Err(VmallocError::OutOfVirtualSpace);
+            }
+            let slot = match top {
+                Some(slot) => slot,
+                None => self.free_slot()?,
+            };
+
+            map_pages(old_break, pages)?;
+            let area = self.areas[slot].get_or_insert(VmArea {
+                start: old_break,
+                pages: 0,
+                from_break: true,
+            });
+            area.pages += pages;
+            self.brk = old_break + pages * PAGE;
+        } else {
+            let slot = top.ok_or(VmallocError::BreakInUse)?;
+            let Some(area) = self.areas[slot].as_mut() else {
+                return Err(VmallocError::BreakInUse);
+            };
+            if pages > area.pages {
+                return Err(VmallocError::BreakInUse);
+            }
+
+            area.pages -= pages;
+            unmap_pages(area.end(), pages);
+            if area.pages == 0 {
+                self.areas[slot] = None;
+            }
+            self.lower_break();
+        }
+        Ok(old_break)
+    }
+}
+
+pub fn vmalloc_stats() -> VmallocStats {
+    without_interrupts(|| {
+        let vmalloc = VMALLOC.lock();
+        VmallocStats {
+            areas: vmalloc.live_areas().count(),
+            mapped: vmalloc.live_areas().map(|area| area.pages * PAGE).sum(),
+            brk: vmalloc.brk,
+        }
+    })
+}
+
+// Virtually contiguous memory in whole pages, backed by frames from anywhere in RAM.
+// Returns null when `size` is 0 or nothing is left to back it.
+pub fn vmalloc(size: usize) -> *mut u8 {
+    if size == 0 {
+        return ptr::null_mut();
+    }
+
+    match without_interrupts(|| VMALLOC.lock().allocate(size)) {
+        Ok(addr) => addr as *mut u8,
+        Err(error) => {
+            printk!(LogLevel::Error, "vmalloc: {} bytes: {}\n", size, error);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Releases a vmalloc area, its pages are unmapped right away.
+///
+/// # Safety
+///
+/// `ptr` must be null or a pointer returned by vmalloc or vbrk. Once freed, neither it nor
+/// any pointer into the area may be used again, as the addresses now fault.
+pub unsafe fn vfree(ptr: *mut u8) {
+    if ptr.is_null() {
+        return;
+    }
+
+    if let Err(error) = without_interrupts(|| VMALLOC.lock().free(ptr as usize)) {
+        printk!(LogLevel::Error, "vfree: {}\n", error);
+    }
+}
+
+// Mapped bytes behind `ptr`, always a whole number of pages, or 0 for unknown pointers
+pub fn vsize(ptr: *const u8) -> usize {
+    match without_interrupts(|| VMALLOC.lock().size(ptr as usize)) {
+        Some(size) => size,
+        None => {
+            printk!(LogLevel::Error, "vsize: {}\n", VmallocError::NotAllocated(ptr as usize));
+            0
+        }
+    }
+}
+
+// Moves the top of the vmalloc zone by `increment` bytes, rounded to whole pages, and
+// returns the old break. Only memory that an earlier vbrk added can be given back.
+pub fn vbrk(increment: isize) -> *mut u8 {
+    match without_interrupts(|| VMALLOC.lock().move_break(increment)) {
+        Ok(old_break) => old_break as *mut u8,
+        Err(error) => {
+            printk!(LogLevel::Error, "vbrk: cannot move the break by {} bytes: {}\n", increment, error);
+            ptr::null_mut()
+        }
+    }
+}
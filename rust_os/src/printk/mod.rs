@@ -1 +1,2 @@
-pub mod printk;
\ No newline at end of file
+pub mod printk;
+pub mod panic;
\ No newline at end of file
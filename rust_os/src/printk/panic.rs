@@ -0,0 +1,58 @@
+use core::fmt::Write;
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+use crate::arch::x86::cpu::{self, Registers};
+use crate::screen::emergency::EmergencyWriter;
+use crate::screen::screen::{Color, ColorCode};
+use super::printk::LOG_SCREEN_ID;
+
+const PANIC_BANNER_COLOR: ColorCode = ColorCode::new(Color::White, Color::Red);
+const PANIC_TEXT_COLOR: ColorCode = ColorCode::new(Color::LightRed, Color::Black);
+
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+pub fn panic_screen(info: &PanicInfo) -> ! {
+    cpu::disable_interrupts();
+    let registers = Registers::capture();
+
+    // A panic while drawing the panic screen would only recurse, stop right away instead
+    if PANICKING.swap(true, Ordering::SeqCst) {
+        cpu::halt_forever();
+    }
+
+    let mut writer = unsafe { EmergencyWriter::on_screen(LOG_SCREEN_ID, PANIC_TEXT_COLOR) };
+
+    writer.set_color(PANIC_BANNER_COLOR);
+    let _ = write!(writer, " KERNEL PANIC ");
+    writer.set_color(PANIC_TEXT_COLOR);
+    let _ = writeln!(writer);
+
+    let _ = writeln!(writer, "{}", info.message());
+    if let Some(location) = info.location() {
+        let _ = writeln!(writer, "  at {}:{}:{}", location.file(), location.line(), location.column());
+    }
+
+    let _ = writeln!(
+        writer,
+        "EAX={:08x} EBX={:08x} ECX={:08x} EDX={:08x}",
+        registers.eax, registers.ebx, registers.ecx, registers.edx
+    );
+    let _ = writeln!(
+        writer,
+        "ESI={:08x} EDI={:08x} EBP={:08x} ESP={:08x}",
+        registers.esi, registers.edi, registers.ebp, registers.esp
+    );
+    let _ = writeln!(
+        writer,
+        "EFLAGS={:08x} CS={:04x} DS={:04x} SS={:04x}",
+        registers.eflags, registers.cs, registers.ds, registers.ss
+    );
+    let _ = writeln!(
+        writer,
+        "CR0={:08x} CR2={:08x} CR3={:08x}",
+        registers.cr0, registers.cr2, registers.cr3
+    );
+    let _ = writeln!(writer, "System halted.");
+
+    cpu::halt_forever();
+}
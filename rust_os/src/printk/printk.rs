@@ -1,6 +1,8 @@
 use core::fmt::{Write, Result};
 use crate::screen::global::screen_manager;
-use crate::screen::screen::Writer;
+use crate::screen::screen::{Color, ColorCode, Writer, DEFAULT_COLOR};
+
+pub const LOG_SCREEN_ID: usize = 1;
 
 #[allow(dead_code)]
 #[derive(Copy, Clone)]
@@ -30,6 +32,19 @@ impl LogLevel {
             LogLevel::Default => "",
         }
     }
+
+    pub fn color(&self) -> ColorCode {
+        match self {
+            LogLevel::Emergency | LogLevel::Alert => ColorCode::new(Color::White, Color::Red),
+            LogLevel::Critical => ColorCode::new(Color::LightRed, Color::Black),
+            LogLevel::Error => ColorCode::new(Color::Red, Color::Black),
+            LogLevel::Warn => ColorCode::new(Color::Yellow, Color::Black),
+            LogLevel::Notice => ColorCode::new(Color::LightCyan, Color::Black),
+            LogLevel::Info => ColorCode::new(Color::LightGray, Color::Black),
+            LogLevel::Debug => ColorCode::new(Color::DarkGray, Color::Black),
+            LogLevel::Default => DEFAULT_COLOR,
+        }
+    }
 }
 
 pub struct Logger {
@@ -51,11 +66,12 @@ impl Logger {
 impl Write for Logger {
     fn write_str(&mut self, s: &str) -> Result {
         let mut manager = screen_manager().lock();
-        let active_screen_id = manager.get_active_screen_id();
         
-        // Write to the currently active screen
-        if let Some(active_screen) = &mut manager.screens[active_screen_id] {
-            let mut writer = Writer::new(active_screen);
+        // Kernel messages always go to the log screen, whichever screen is displayed
+        if let Some(log_screen) = manager.get_screen_mut(LOG_SCREEN_ID) {
+            let mut writer = Writer::new(log_screen);
+            let previous_color = writer.color();
+            writer.set_color(self.level.color());
             
             if self.loglvl_write_flag == false {
                 for byte in self.level.as_str().bytes() {
@@ -74,9 +90,12 @@ impl Write for Logger {
                     self.loglvl_write_flag = false;
                 }
             }
+            writer.set_color(previous_color);
 
-            manager.flush_to_physical();
-            manager.update_cursor();
+            if manager.get_active_screen_id() == LOG_SCREEN_ID {
+                manager.flush_to_physical();
+                manager.update_cursor();
+            }
         }
         
         Ok(())
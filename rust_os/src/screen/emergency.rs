@@ -0,0 +1,117 @@
+use core::fmt::{Write, Result};
+use core::ptr;
+use super::global::screen_manager_unchecked;
+use super::screen::{Buffer, ColorCode, ScreenChar, BUFFER_HEIGHT, BUFFER_WIDTH, VGA_BUFFER_ADDRESS};
+
+// Writes straight into VGA memory. Nothing here takes a lock, so it keeps working
+// when the code that failed was holding the screen manager.
+pub struct EmergencyWriter {
+    buffer: *mut Buffer,
+    row: usize,
+    column: usize,
+    color_code: ColorCode,
+}
+
+impl EmergencyWriter {
+    /// Brings `screen_id` to the front and continues below its last line, so the messages
+    /// leading up to the failure stay visible.
+    ///
+    /// # Safety
+    ///
+    /// Reads and switches the screen manager through `screen_manager_unchecked`, and the
+    /// writer then draws into VGA memory without any lock. The caller must guarantee that
+    /// no other code uses the screen manager or the VGA buffer from then on, which holds
+    /// once interrupts are disabled on the way to halting.
+    pub unsafe fn on_screen(screen_id: usize, color_code: ColorCode) -> Self {
+        let mut writer = Self {
+            buffer: VGA_BUFFER_ADDRESS as *mut Buffer,
+            row: 0,
+            column: 0,
+            color_code,
+        };
+
+        match screen_manager_unchecked() {
+            Some(manager) if manager.get_screen(screen_id).is_some() => {
+                manager.active_screen_id = screen_id;
+                manager.flush_to_physical();
+
+                let screen = manager.get_active_screen();
+                writer.row = screen.row_position;
+                if screen.column_position != 0 {
+                    writer.row += 1;
+                }
+            }
+            _ => writer.clear(),
+        }
+        writer
+    }
+
+    pub fn set_color(&mut self, color_code: ColorCode) {
+        self.color_code = color_code;
+    }
+
+    pub fn write_byte(&mut self, byte: u8) {
+        if byte == b'\n' {
+            self.row += 1;
+            self.column = 0;
+            return;
+        }
+
+        if self.row >= BUFFER_HEIGHT {
+            self.scroll_up();
+            self.row = BUFFER_HEIGHT - 1;
+        }
+
+        self.put(self.row, self.column, byte);
+
+        self.column += 1;
+        if self.column >= BUFFER_WIDTH {
+            self.column = 0;
+            self.row += 1;
+        }
+    }
+
+    fn put(&mut self, row: usize, col: usize, byte: u8) {
+        let screen_char = ScreenChar {
+            ascii_character: byte,
+            color_code: self.color_code,
+        };
+        unsafe {
+            ptr::write_volatile(&raw mut (*self.buffer).chars[row][col], screen_char);
+        }
+    }
+
+    fn clear(&mut self) {
+        for row in 0..BUFFER_HEIGHT {
+            for col in 0..BUFFER_WIDTH {
+                self.put(row, col, b' ');
+            }
+        }
+        self.row = 0;
+        self.column = 0;
+    }
+
+    fn scroll_up(&mut self) {
+        unsafe {
+            for row in 1..BUFFER_HEIGHT {
+                for col in 0..BUFFER_WIDTH {
+                    let screen_char = ptr::read_volatile(&raw const (*self.buffer).chars[row][col]);
+                    ptr::write_volatile(&raw mut (*self.buffer).chars[row - 1][col], screen_char);
+                }
+            }
+        }
+
+        for col in 0..BUFFER_WIDTH {
+            self.put(BUFFER_HEIGHT - 1, col, b' ');
+        }
+    }
+}
+
+impl Write for EmergencyWriter {
+    fn write_str(&mut self, s: &str) -> Result {
+        for byte in s.bytes() {
+            self.write_byte(byte);
+        }
+        Ok(())
+    }
+}
@@ -1,15 +1,18 @@
 use core::mem::MaybeUninit;
 use core::ptr::addr_of;
+use core::sync::atomic::{AtomicBool, Ordering};
 use crate::printk;
 use super::manager::ScreenManager;
 use crate::kspin_lock::kspin_lock::KSpinLock;
 
 static mut SCREEN_MANAGER: MaybeUninit<KSpinLock<ScreenManager>> = MaybeUninit::uninit();
+static SCREEN_MANAGER_READY: AtomicBool = AtomicBool::new(false);
 
 pub fn init_screen_manager() {
     unsafe {
         SCREEN_MANAGER = MaybeUninit::new(KSpinLock::new(ScreenManager::new()));
     }
+    SCREEN_MANAGER_READY.store(true, Ordering::Release);
     
     {
         let mut manager = screen_manager().lock();
@@ -32,4 +35,20 @@ pub fn screen_manager() -> &'static KSpinLock<ScreenManager> {
         let ptr = addr_of!(SCREEN_MANAGER);
         (*ptr).assume_init_ref()
     }
-}
\ No newline at end of file
+}
+
+/// Skips the lock entirely: only meant for the panic path, which may have interrupted
+/// the holder of the lock. Returns None before init_screen_manager has run.
+///
+/// # Safety
+///
+/// The returned reference aliases whatever `screen_manager().lock()` guard is live, so the
+/// caller must make sure nothing else touches the manager while it is in use: interrupts
+/// are off and the code that held the lock will never run again, as on the panic path.
+/// The reference must not be kept past that point.
+pub unsafe fn screen_manager_unchecked() -> Option<&'static mut ScreenManager> {
+    if !SCREEN_MANAGER_READY.load(Ordering::Acquire) {
+        return None;
+    }
+    Some(&mut *screen_manager().data_ptr())
+}
@@ -1,5 +1,5 @@
 use crate::arch::x86::port::outb;
-use super::screen::{ Buffer, Screen, BUFFER_HEIGHT, BUFFER_WIDTH };
+use super::screen::{ Buffer, Screen, BUFFER_HEIGHT, BUFFER_WIDTH, VGA_BUFFER_ADDRESS };
 
 const MAX_SCREENS: usize = 2;
 
@@ -15,7 +15,7 @@ impl ScreenManager {
             screens: core::array::from_fn(|i| Some(Screen::new(i + 1))),
             active_screen_id: 1,
             physical_buffer: unsafe {
-                &mut *(0xb8000 as *mut Buffer)
+                &mut *(VGA_BUFFER_ADDRESS as *mut Buffer)
             },
         }
     }
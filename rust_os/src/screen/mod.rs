@@ -1,3 +1,4 @@
 pub mod manager;
 pub mod screen;
-pub mod global; 
\ No newline at end of file
+pub mod global; 
+pub mod emergency;
\ No newline at end of file
@@ -1,14 +1,131 @@
 use core::fmt::{Write, Result};
+use crate::memory::address::KERNEL_OFFSET;
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Color {
+    Black = 0,
+    Blue = 1,
+    Green = 2,
+    Cyan = 3,
+    Red = 4,
+    Magenta = 5,
+    Brown = 6,
+    LightGray = 7,
+    DarkGray = 8,
+    LightBlue = 9,
+    LightGreen = 10,
+    LightCyan = 11,
+    LightRed = 12,
+    Pink = 13,
+    Yellow = 14,
+    White = 15,
+}
+
+// VGA attribute byte: background in the high nibble, foreground in the low one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct ColorCode(u8);
+
+impl ColorCode {
+    pub const fn new(foreground: Color, background: Color) -> Self {
+        ColorCode((background as u8) << 4 | (foreground as u8))
+    }
+
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub const fn with_foreground(self, foreground: Color) -> Self {
+        ColorCode((self.0 & 0xf0) | foreground as u8)
+    }
+
+    pub const fn with_background(self, background: Color) -> Self {
+        ColorCode((self.0 & 0x0f) | (background as u8) << 4)
+    }
+}
+
+pub const DEFAULT_COLOR: ColorCode = ColorCode::new(Color::White, Color::Black);
+
+const ESC: u8 = 0x1b;
+const MAX_CSI_PARAMS: usize = 8;
+const BRIGHT: u8 = 0x08;
+
+// SGR colour numbers 0-7 in ANSI order, which differs from the VGA palette
+const ANSI_COLORS: [Color; 8] = [
+    Color::Black,
+    Color::Red,
+    Color::Green,
+    Color::Brown,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::LightGray,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AnsiState {
+    Ground,
+    Escape,
+    Csi,
+}
+
+// Escape sequence decoding state. It lives on the screen rather than in the Writer since
+// a sequence can be split over several writes.
+#[derive(Debug, Clone, Copy)]
+struct AnsiParser {
+    state: AnsiState,
+    params: [u16; MAX_CSI_PARAMS],
+    // Parameters started so far, can run past MAX_CSI_PARAMS, the extra ones are dropped
+    param_count: usize,
+    // Sequences such as ESC[?25l are recognised and skipped
+    private: bool,
+    bold: bool,
+    saved_cursor: (usize, usize),
+}
+
+impl AnsiParser {
+    const fn new() -> Self {
+        Self {
+            state: AnsiState::Ground,
+            params: [0; MAX_CSI_PARAMS],
+            param_count: 0,
+            private: false,
+            bold: false,
+            saved_cursor: (0, 0),
+        }
+    }
+
+    // Missing and zero parameters both take the default, as VT100 does
+    fn param(&self, index: usize, default: usize) -> usize {
+        match self.params.get(index) {
+            Some(&value) if index < self.param_count && value != 0 => value as usize,
+            _ => default,
+        }
+    }
+}
 
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub struct ScreenChar {
     pub ascii_character: u8,
-    pub color_code: u8,
+    pub color_code: ColorCode,
+}
+
+impl ScreenChar {
+    pub const fn blank(color_code: ColorCode) -> Self {
+        ScreenChar {
+            ascii_character: b' ',
+            color_code,
+        }
+    }
 }
 
 pub const BUFFER_HEIGHT: usize = 25;
 pub const BUFFER_WIDTH: usize = 80;
+// Text mode memory at physical 0xb8000, reached through the kernel's mapping
+pub const VGA_BUFFER_ADDRESS: usize = KERNEL_OFFSET + 0xb8000;
 
 #[repr(transparent)]
 pub struct Buffer {
@@ -18,10 +135,7 @@ pub struct Buffer {
 impl Buffer {
     pub fn new() -> Self {
         Buffer {
-            chars: [[ScreenChar {
-                ascii_character: b' ',
-                color_code: 0x0f,
-            }; BUFFER_WIDTH]; BUFFER_HEIGHT],
+            chars: [[ScreenChar::blank(DEFAULT_COLOR); BUFFER_WIDTH]; BUFFER_HEIGHT],
         }
     }
 }
@@ -30,7 +144,10 @@ pub struct Screen {
     pub id: usize,
     pub column_position: usize,
     pub row_position: usize,
+    // Attribute for everything written from now on, blanks included
+    pub color_code: ColorCode,
     pub buffer: Buffer,
+    ansi: AnsiParser,
 }
 
 impl Screen {
@@ -39,15 +156,14 @@ impl Screen {
             id,
             column_position: 0,
             row_position: 0,
-            buffer: Buffer::new()
+            color_code: DEFAULT_COLOR,
+            buffer: Buffer::new(),
+            ansi: AnsiParser::new(),
         }
     }
     
     pub fn clear(&mut self) {
-        let blank = ScreenChar {
-            ascii_character: b' ',
-            color_code: 0x0f
-        };
+        let blank = ScreenChar::blank(self.color_code);
         
         for row in 0..BUFFER_HEIGHT {
             for col in 0..BUFFER_WIDTH {
@@ -79,6 +195,14 @@ impl Screen {
         &mut self.buffer
     }
 
+    pub fn color(&self) -> ColorCode {
+        self.color_code
+    }
+
+    pub fn set_color(&mut self, color_code: ColorCode) {
+        self.color_code = color_code;
+    }
+
     pub fn set_cursor_position(&mut self, row: usize, col: usize) {
         self.row_position = row;
         self.column_position = col;
@@ -88,7 +212,7 @@ impl Screen {
         if row < BUFFER_HEIGHT && col < BUFFER_WIDTH {
             self.buffer.chars[row][col] = ScreenChar {
                 ascii_character: byte,
-                color_code: 0x0f,
+                color_code: self.color_code,
             };
         }
     }
@@ -106,7 +230,7 @@ impl Screen {
             self.buffer.chars[self.row_position][self.column_position] =
                 ScreenChar {
                     ascii_character: byte,
-                    color_code: 0x0f,
+                    color_code: self.color_code,
                 };
 
             self.column_position += 1;
@@ -122,11 +246,7 @@ impl Screen {
             self.buffer.chars[row - 1] = self.buffer.chars[row];
         }
 
-        self.buffer.chars[BUFFER_HEIGHT - 1] =
-            [ScreenChar {
-                ascii_character: b' ',
-                color_code: 0x0f,
-            }; BUFFER_WIDTH];
+        self.buffer.chars[BUFFER_HEIGHT - 1] = [ScreenChar::blank(self.color_code); BUFFER_WIDTH];
     }
 }
 
@@ -139,40 +259,194 @@ impl<'a> Writer<'a> {
         Self { screen }
     }
 
+    pub fn color(&self) -> ColorCode {
+        self.screen.color()
+    }
+
+    // Applies to the rest of the stream and stays on the screen afterwards, callers that
+    // only want a few words coloured put the previous code back themselves
+    pub fn set_color(&mut self, color_code: ColorCode) {
+        self.screen.set_color(color_code);
+    }
+
+    pub fn set_foreground(&mut self, foreground: Color) {
+        self.screen.color_code = self.screen.color_code.with_foreground(foreground);
+    }
+
+    // Printable bytes and newlines go to Screen::write_byte, which owns wrapping and
+    // scrolling. ESC starts a sequence that is interpreted instead of shown. The supported
+    // CSI commands are:
+    //   m      SGR: 0 reset, 1 bold, 22 normal, 30-37/90-97 and 39 foreground,
+    //          40-47/100-107 and 49 background
+    //   H, f   CUP: move to row;column, both counted from 1
+    //   K      EL: erase 0 to the end of the line, 1 from its start, 2 all of it
+    //   J      ED: the same for the whole screen
+    //   s, u   save and restore the cursor, ESC 7 and ESC 8 do the same
     pub fn write_byte(&mut self, byte: u8) {
-        if byte == b'\n' {
-            self.screen.row_position += 1;
-            self.screen.column_position = 0;
-        } else {
-            if self.screen.row_position >= BUFFER_HEIGHT {
-                self.scroll_up();
-                self.screen.row_position = BUFFER_HEIGHT - 1;
+        match self.screen.ansi.state {
+            AnsiState::Ground if byte == ESC => self.screen.ansi.state = AnsiState::Escape,
+            AnsiState::Ground => self.screen.write_byte(byte),
+            AnsiState::Escape => self.escape(byte),
+            AnsiState::Csi => self.csi(byte),
+        }
+    }
+
+    fn escape(&mut self, byte: u8) {
+        let ansi = &mut self.screen.ansi;
+        ansi.state = AnsiState::Ground;
+        match byte {
+            b'[' => {
+                ansi.state = AnsiState::Csi;
+                ansi.params = [0; MAX_CSI_PARAMS];
+                ansi.param_count = 0;
+                ansi.private = false;
             }
+            b'7' => self.save_cursor(),
+            b'8' => self.restore_cursor(),
+            _ => {}
+        }
+    }
 
-            self.screen.buffer.chars[self.screen.row_position][self.screen.column_position] =
-                ScreenChar {
-                    ascii_character: byte,
-                    color_code: 0x0f,
-                };
+    fn csi(&mut self, byte: u8) {
+        let ansi = &mut self.screen.ansi;
+        match byte {
+            b'0'..=b'9' => {
+                ansi.param_count = ansi.param_count.max(1);
+                if let Some(param) = ansi.params.get_mut(ansi.param_count - 1) {
+                    *param = param.saturating_mul(10).saturating_add((byte - b'0') as u16);
+                }
+            }
+            b';' => ansi.param_count = ansi.param_count.max(1) + 1,
+            b'<'..=b'?' => ansi.private = true,
+            // Intermediate bytes, none of the supported commands use them
+            0x20..=0x2f => {}
+            0x40..=0x7e => {
+                ansi.state = AnsiState::Ground;
+                if !ansi.private {
+                    self.dispatch_csi(byte);
+                }
+            }
+            // Anything else is malformed, drop the sequence
+            _ => ansi.state = AnsiState::Ground,
+        }
+    }
 
-            self.screen.column_position += 1;
-            if self.screen.column_position >= BUFFER_WIDTH {
-                self.screen.column_position = 0;
-                self.screen.row_position += 1;
+    fn dispatch_csi(&mut self, command: u8) {
+        let ansi = self.screen.ansi;
+        match command {
+            b'm' => self.select_graphic_rendition(),
+            b'H' | b'f' => {
+                let row = ansi.param(0, 1).min(BUFFER_HEIGHT) - 1;
+                let col = ansi.param(1, 1).min(BUFFER_WIDTH) - 1;
+                self.screen.set_cursor_position(row, col);
             }
+            b'K' => self.erase_in_line(ansi.param(0, 0)),
+            b'J' => self.erase_in_display(ansi.param(0, 0)),
+            b's' => self.save_cursor(),
+            b'u' => self.restore_cursor(),
+            _ => {}
         }
     }
 
-    pub fn scroll_up(&mut self) {
-        for row in 1..BUFFER_HEIGHT {
-            self.screen.buffer.chars[row - 1] = self.screen.buffer.chars[row];
+    fn select_graphic_rendition(&mut self) {
+        let ansi = self.screen.ansi;
+        let mut color = self.screen.color_code;
+        let mut bold = ansi.bold;
+
+        // ESC[m is the same as ESC[0m
+        for index in 0..ansi.param_count.clamp(1, MAX_CSI_PARAMS) {
+            let code = ansi.params[index] as usize;
+            match code {
+                0 => {
+                    color = DEFAULT_COLOR;
+                    bold = false;
+                }
+                1 => {
+                    bold = true;
+                    color = ColorCode(color.0 | BRIGHT);
+                }
+                22 => {
+                    bold = false;
+                    color = ColorCode(color.0 & !BRIGHT);
+                }
+                30..=37 => {
+                    color = color.with_foreground(ANSI_COLORS[code - 30]);
+                    if bold {
+                        color = ColorCode(color.0 | BRIGHT);
+                    }
+                }
+                39 => color = ColorCode((color.0 & 0xf0) | (DEFAULT_COLOR.0 & 0x0f)),
+                90..=97 => color = ColorCode(color.with_foreground(ANSI_COLORS[code - 90]).0 | BRIGHT),
+                40..=47 => color = color.with_background(ANSI_COLORS[code - 40]),
+                // The high background bit makes VGA text blink, so bright backgrounds
+                // fall back to the normal ones
+                100..=107 => color = color.with_background(ANSI_COLORS[code - 100]),
+                49 => color = ColorCode((color.0 & 0x0f) | (DEFAULT_COLOR.0 & 0xf0)),
+                _ => {}
+            }
         }
 
-        self.screen.buffer.chars[BUFFER_HEIGHT - 1] =
-            [ScreenChar {
-                ascii_character: b' ',
-                color_code: 0x0f,
-            }; BUFFER_WIDTH];
+        self.screen.color_code = color;
+        self.screen.ansi.bold = bold;
+    }
+
+    // A row_position of BUFFER_HEIGHT means the next byte scrolls, treat it as the last row
+    fn cursor_cell(&self) -> (usize, usize) {
+        (
+            self.screen.row_position.min(BUFFER_HEIGHT - 1),
+            self.screen.column_position.min(BUFFER_WIDTH - 1),
+        )
+    }
+
+    fn blank_cells(&mut self, row: usize, cols: core::ops::Range<usize>) {
+        let blank = ScreenChar::blank(self.screen.color_code);
+        for col in cols {
+            self.screen.buffer.chars[row][col] = blank;
+        }
+    }
+
+    fn erase_in_line(&mut self, mode: usize) {
+        let (row, col) = self.cursor_cell();
+        match mode {
+            0 => self.blank_cells(row, col..BUFFER_WIDTH),
+            1 => self.blank_cells(row, 0..col + 1),
+            2 => self.blank_cells(row, 0..BUFFER_WIDTH),
+            _ => {}
+        }
+    }
+
+    // Like EL the cursor does not move, ESC[2J is usually followed by ESC[H
+    fn erase_in_display(&mut self, mode: usize) {
+        let (row, col) = self.cursor_cell();
+        let rows = match mode {
+            0 => {
+                self.blank_cells(row, col..BUFFER_WIDTH);
+                row + 1..BUFFER_HEIGHT
+            }
+            1 => {
+                self.blank_cells(row, 0..col + 1);
+                0..row
+            }
+            // 3 also drops the scrollback, of which there is none
+            2 | 3 => 0..BUFFER_HEIGHT,
+            _ => 0..0,
+        };
+        for row in rows {
+            self.blank_cells(row, 0..BUFFER_WIDTH);
+        }
+    }
+
+    fn save_cursor(&mut self) {
+        self.screen.ansi.saved_cursor = (self.screen.row_position, self.screen.column_position);
+    }
+
+    fn restore_cursor(&mut self) {
+        let (row, col) = self.screen.ansi.saved_cursor;
+        self.screen.set_cursor_position(row.min(BUFFER_HEIGHT - 1), col.min(BUFFER_WIDTH - 1));
+    }
+
+    pub fn scroll_up(&mut self) {
+        self.screen.scroll_up();
     }
 }
 